@@ -1,14 +1,16 @@
 //! I/O streams for wrapping `BufRead` types as decoders
 
+use std::io;
 use std::io::prelude::*;
-use std::{io, mem};
 
 #[cfg(feature = "tokio")]
 use futures::Poll;
 #[cfg(feature = "tokio")]
 use tokio_io::{AsyncRead, AsyncWrite};
 
-use mem::Decompress;
+use format::{Header, Trailer, HEADER_SIZE};
+use mem::{Decompress, Error, Status};
+use recover::Damage;
 
 /// A lz decoder, or decompressor.
 ///
@@ -18,7 +20,19 @@ pub struct LzDecoder<R> {
     obj: R,
     data: Decompress,
     done: bool,
+    eof: bool,
     multi: bool,
+    members: u64,
+    recovery: Option<Recovery>,
+}
+
+/// Damage found so far by a recovering decoder.
+struct Recovery {
+    damage: Vec<Damage>,
+    // The damage whose end is not known until the next member is found.
+    pending: Option<Damage>,
+    // Output offset of the start of the current member.
+    data_offset: u64,
 }
 
 impl<R: BufRead> LzDecoder<R> {
@@ -27,9 +41,12 @@ impl<R: BufRead> LzDecoder<R> {
     pub fn new(r: R) -> LzDecoder<R> {
         LzDecoder {
             obj: r,
-            data: Decompress::new(false),
+            data: Decompress::new(),
             done: false,
+            eof: false,
             multi: false,
+            members: 0,
+            recovery: None,
         }
     }
 
@@ -37,6 +54,60 @@ impl<R: BufRead> LzDecoder<R> {
         self.multi = flag;
         self
     }
+
+    fn recover(mut self) -> LzDecoder<R> {
+        self.recovery = Some(Recovery {
+            damage: Vec::new(),
+            pending: None,
+            data_offset: 0,
+        });
+        self
+    }
+
+    /// Records a damaged member and skips to the next member header.
+    fn skip_damage(&mut self, error: Error) -> io::Result<()> {
+        let member_offset = self.data.member_offset();
+        let total_out = self.data.total_out();
+        let recovery = self.recovery.as_mut().unwrap();
+        recovery.end_damage(member_offset);
+        recovery.pending = Some(Damage {
+            member_offset,
+            resync_offset: member_offset,
+            data_offset: recovery.data_offset,
+            data_size: total_out - recovery.data_offset,
+            error,
+        });
+        recovery.data_offset = total_out;
+        self.data.sync_to_member().map_err(io_error)
+    }
+
+    /// Limits the dictionary size that a member header may declare.
+    ///
+    /// Each member header is inspected before its dictionary is allocated,
+    /// and reading fails with `Error::DictionaryTooLarge` if the declared
+    /// size exceeds `limit`.
+    pub fn max_dictionary_size(mut self, limit: u32) -> LzDecoder<R> {
+        self.data.set_max_dictionary_size(Some(limit));
+        self
+    }
+
+    /// Limits the total number of decompressed bytes.
+    ///
+    /// Reading fails with `Error::OutputTooLarge` once `limit` bytes have
+    /// been produced and more data would follow.
+    pub fn max_output_size(mut self, limit: u64) -> LzDecoder<R> {
+        self.data.set_max_output_size(Some(limit));
+        self
+    }
+
+    /// Limits the expansion ratio of decompressed to compressed bytes.
+    ///
+    /// Reading fails with `Error::RatioTooLarge` if the output cannot be
+    /// kept within `limit` times the compressed input read so far.
+    pub fn max_ratio(mut self, limit: u64) -> LzDecoder<R> {
+        self.data.set_max_ratio(Some(limit));
+        self
+    }
 }
 
 impl<R> LzDecoder<R> {
@@ -57,54 +128,135 @@ impl<R> LzDecoder<R> {
     pub fn into_inner(self) -> R {
         self.obj
     }
+
+    /// Returns the number of bytes produced by the decompressor
+    /// (e.g. the number of bytes read from this stream)
+    pub fn total_out(&self) -> u64 {
+        self.data.total_out()
+    }
+
+    /// Returns the number of bytes consumed by the decompressor
+    /// (e.g. the number of bytes read from the underlying stream)
+    pub fn total_in(&self) -> u64 {
+        self.data.total_in()
+    }
 }
 
 impl<R: BufRead> Read for LzDecoder<R> {
     fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
         loop {
-            if self.done && !self.multi {
+            if self.done {
                 return Ok(0);
             }
-            let (read, consumed, remaining, ret);
-            {
-                let input = self.obj.fill_buf()?;
-                if self.done {
-                    assert!(self.multi);
-                    if input.is_empty() {
-                        // beyond last stream in multi-stream case
-                        return Ok(0);
-                    } else {
-                        // previous stream ended, more data follows => create new decompressor
-                        self.data = Decompress::new(false);
-                        self.done = false;
+            let (read, ret) = decompress_step(&mut self.obj, &mut self.data, &mut self.eof, buf)?;
+            match ret {
+                Ok(Status::StreamEnd) => {
+                    self.done = true;
+                    if let Some(ref mut recovery) = self.recovery {
+                        recovery.end_damage(self.data.total_in());
                     }
                 }
-                let before_out = lzip_sys::LZ_decompress_total_out_size(mem::zeroed());
-                let before_in = self.data.total_in();
-                ret = self.data.decompress(input, buf);
-                read = (self.data.total_out() - before_out) as usize;
-                consumed = (self.data.total_in() - before_in) as usize;
-                remaining = input.len() - consumed;
-            }
-            self.obj.consume(consumed);
-
-            let ret = ret.map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
-            if ret == Status::StreamEnd {
-                self.done = true;
-            } else if consumed == 0 && remaining == 0 && read == 0 {
-                return Err(io::Error::new(
-                    io::ErrorKind::UnexpectedEof,
-                    "decompression not finished but EOF reached",
-                ));
+                Ok(Status::MemberEnd) => {
+                    self.members += 1;
+                    self.done = !self.multi;
+                    if let Some(ref mut recovery) = self.recovery {
+                        recovery.end_damage(self.data.member_offset());
+                        recovery.data_offset = self.data.total_out();
+                    }
+                }
+                Ok(Status::MemNeeded) if self.eof && read == 0 => {
+                    return Err(decode_error(
+                        &self.data,
+                        self.members,
+                        self.data.unexpected_eof(),
+                    ))
+                }
+                Ok(_) => {}
+                // Like lzip, ignore trailing data after the last member.
+                Err(Error::DataMagic) if self.members > 0 => self.done = true,
+                Err(e) if self.recovery.is_some() && is_damage(e) => self.skip_damage(e)?,
+                Err(e) => return Err(decode_error(&self.data, self.members, e)),
             }
 
-            if read > 0 || buf.len() == 0 {
+            if read > 0 || buf.is_empty() {
                 return Ok(read);
             }
         }
     }
 }
 
+impl Recovery {
+    /// Completes the pending damage, if any, now that decoding resumed at
+    /// `offset`.
+    fn end_damage(&mut self, offset: u64) {
+        if let Some(mut damage) = self.pending.take() {
+            damage.resync_offset = offset;
+            self.damage.push(damage);
+        }
+    }
+}
+
+/// Returns true for the errors a recovering decoder skips over.
+fn is_damage(e: Error) -> bool {
+    matches!(
+        e,
+        Error::Data
+            | Error::DataMagic
+            | Error::CorruptHeader
+            | Error::Format(_)
+            | Error::UnexpectedEof { .. }
+    )
+}
+
+/// Runs `data` once on the input available from `r`, decompressing into
+/// `buf`, and consumes the input it accepted. Returns the number of bytes
+/// decompressed along with the result of `Decompress::decompress`.
+///
+/// Once `r` runs dry the stream is told that all input has been provided,
+/// and `eof` is set. A stream that failed can't be finished; it reports its
+/// error from `decompress` once the data decoded before the error has been
+/// read, so `eof` is left unset then.
+pub(crate) fn decompress_step<R: BufRead>(
+    r: &mut R,
+    data: &mut Decompress,
+    eof: &mut bool,
+    buf: &mut [u8],
+) -> io::Result<(usize, Result<Status, Error>)> {
+    let (read, consumed, ret);
+    {
+        let input = r.fill_buf()?;
+        if input.is_empty() && !*eof && data.finish().is_ok() {
+            *eof = true;
+        }
+        let before_out = data.total_out();
+        let before_in = data.total_in();
+        ret = data.decompress(input, buf);
+        read = (data.total_out() - before_out) as usize;
+        consumed = (data.total_in() - before_in) as usize;
+    }
+    r.consume(consumed);
+    Ok((read, ret))
+}
+
+pub(crate) fn io_error(e: Error) -> io::Error {
+    io::Error::new(error_kind(e), e)
+}
+
+/// Wraps an error in the data of member number `member` with the position
+/// `data` reached when it was found.
+pub(crate) fn decode_error(data: &Decompress, member: u64, error: Error) -> io::Error {
+    io::Error::new(error_kind(error), data.decode_error(member, error))
+}
+
+pub(crate) fn error_kind(e: Error) -> io::ErrorKind {
+    match e {
+        Error::UnexpectedEof { .. } => io::ErrorKind::UnexpectedEof,
+        Error::Sequence | Error::Param => io::ErrorKind::InvalidInput,
+        Error::Mem | Error::Library => io::ErrorKind::Other,
+        _ => io::ErrorKind::InvalidData,
+    }
+}
+
 #[cfg(feature = "tokio")]
 impl<R: AsyncRead + BufRead> AsyncRead for LzDecoder<R> {}
 
@@ -137,6 +289,27 @@ impl<R: BufRead> MultiLzDecoder<R> {
     pub fn new(r: R) -> MultiLzDecoder<R> {
         MultiLzDecoder(LzDecoder::new(r).multi(true))
     }
+
+    /// Limits the dictionary size that a member header may declare.
+    ///
+    /// See `LzDecoder::max_dictionary_size`.
+    pub fn max_dictionary_size(self, limit: u32) -> MultiLzDecoder<R> {
+        MultiLzDecoder(self.0.max_dictionary_size(limit))
+    }
+
+    /// Limits the total number of decompressed bytes.
+    ///
+    /// See `LzDecoder::max_output_size`.
+    pub fn max_output_size(self, limit: u64) -> MultiLzDecoder<R> {
+        MultiLzDecoder(self.0.max_output_size(limit))
+    }
+
+    /// Limits the expansion ratio of decompressed to compressed bytes.
+    ///
+    /// See `LzDecoder::max_ratio`.
+    pub fn max_ratio(self, limit: u64) -> MultiLzDecoder<R> {
+        MultiLzDecoder(self.0.max_ratio(limit))
+    }
 }
 
 impl<R> MultiLzDecoder<R> {
@@ -184,3 +357,283 @@ impl<R: AsyncWrite + BufRead> AsyncWrite for MultiLzDecoder<R> {
         self.get_mut().shutdown()
     }
 }
+
+/// An lzip decoder that skips damaged members instead of failing.
+///
+/// All members are decoded like with `MultiLzDecoder`. When a member turns
+/// out to be damaged, the damage is recorded, the decoder resynchronizes at
+/// the next member header and decoding continues from there. Data of a
+/// damaged member that was output before the error was found is kept, see
+/// `Damage::data_size`. Once the end of the stream is reached, `damage`
+/// lists every damaged region.
+pub struct RecoveringLzDecoder<R>(LzDecoder<R>);
+
+impl<R: BufRead> RecoveringLzDecoder<R> {
+    /// Creates a new recovering decoder reading from the given reader.
+    pub fn new(r: R) -> RecoveringLzDecoder<R> {
+        RecoveringLzDecoder(LzDecoder::new(r).multi(true).recover())
+    }
+
+    /// Limits the dictionary size that a member header may declare.
+    ///
+    /// See `LzDecoder::max_dictionary_size`.
+    pub fn max_dictionary_size(self, limit: u32) -> RecoveringLzDecoder<R> {
+        RecoveringLzDecoder(self.0.max_dictionary_size(limit))
+    }
+
+    /// Limits the total number of decompressed bytes.
+    ///
+    /// See `LzDecoder::max_output_size`.
+    pub fn max_output_size(self, limit: u64) -> RecoveringLzDecoder<R> {
+        RecoveringLzDecoder(self.0.max_output_size(limit))
+    }
+
+    /// Limits the expansion ratio of decompressed to compressed bytes.
+    ///
+    /// See `LzDecoder::max_ratio`.
+    pub fn max_ratio(self, limit: u64) -> RecoveringLzDecoder<R> {
+        RecoveringLzDecoder(self.0.max_ratio(limit))
+    }
+}
+
+impl<R> RecoveringLzDecoder<R> {
+    /// Returns the damaged regions found so far, in the order they appear.
+    ///
+    /// The last damaged region is only listed once decoding has resumed
+    /// after it or the end of the stream has been reached.
+    pub fn damage(&self) -> &[Damage] {
+        &self.0.recovery.as_ref().unwrap().damage
+    }
+
+    /// Acquires a reference to the underlying reader.
+    pub fn get_ref(&self) -> &R {
+        self.0.get_ref()
+    }
+
+    /// Acquires a mutable reference to the underlying stream.
+    ///
+    /// Note that mutation of the stream may result in surprising results if
+    /// this decoder is continued to be used.
+    pub fn get_mut(&mut self) -> &mut R {
+        self.0.get_mut()
+    }
+
+    /// Consumes this decoder, returning the underlying reader.
+    pub fn into_inner(self) -> R {
+        self.0.into_inner()
+    }
+
+    /// Returns the number of bytes produced by the decompressor.
+    pub fn total_out(&self) -> u64 {
+        self.0.total_out()
+    }
+
+    /// Returns the number of bytes consumed by the decompressor.
+    pub fn total_in(&self) -> u64 {
+        self.0.total_in()
+    }
+}
+
+impl<R: BufRead> Read for RecoveringLzDecoder<R> {
+    fn read(&mut self, into: &mut [u8]) -> io::Result<usize> {
+        self.0.read(into)
+    }
+}
+
+/// A reader over the members of a multi-member lzip stream, one at a time.
+///
+/// Unlike `MultiLzDecoder`, which joins the data of all members together,
+/// this hands out a `MemberReader` per member, so that the boundaries
+/// between members stay visible.
+///
+/// # Example
+///
+/// ```no_run
+/// use std::fs::File;
+/// use std::io::{self, BufReader};
+/// use lzip::bufread::Members;
+///
+/// let file = File::open("archive.lz").unwrap();
+/// let mut members = Members::new(BufReader::new(file));
+/// while let Some(mut member) = members.next_member().unwrap() {
+///     let size = io::copy(&mut member, &mut io::sink()).unwrap();
+///     let trailer = member.trailer().unwrap();
+///     println!("{} bytes, crc {:08x}", size, trailer.data_crc);
+/// }
+/// ```
+pub struct Members<R> {
+    obj: R,
+    data: Decompress,
+    eof: bool,
+    members: u64,
+    state: MemberState,
+}
+
+enum MemberState {
+    /// No member has been started yet.
+    Start,
+    /// Inside a member whose data hasn't been fully read.
+    Data,
+    /// At the end of a member, whose trailer has been verified.
+    End(Trailer),
+    /// There are no more members.
+    Done,
+}
+
+/// The decompressed data of a single member, handed out by
+/// `Members::next_member`.
+pub struct MemberReader<'a, R: 'a> {
+    members: &'a mut Members<R>,
+    header: Header,
+    member_offset: u64,
+}
+
+impl<R: BufRead> Members<R> {
+    /// Creates a new reader over the members of the stream read from `r`.
+    pub fn new(r: R) -> Members<R> {
+        Members {
+            obj: r,
+            data: Decompress::new(),
+            eof: false,
+            members: 0,
+            state: MemberState::Start,
+        }
+    }
+
+    /// Advances to the next member, returning `None` at the end of the
+    /// stream.
+    ///
+    /// Whatever is left of the data of the current member is read and
+    /// discarded first. Like lzip, trailing data after the last member is
+    /// ignored.
+    pub fn next_member(&mut self) -> io::Result<Option<MemberReader<'_, R>>> {
+        let mut scratch = [0; 8192];
+        while let MemberState::Data = self.state {
+            let (read, status) = self.step(&mut scratch)?;
+            if status == Status::MemNeeded && self.eof && read == 0 {
+                return Err(self.error(self.data.unexpected_eof()));
+            }
+        }
+        if let MemberState::Done = self.state {
+            return Ok(None);
+        }
+
+        // Feed input until lzlib has read the header of the next member.
+        loop {
+            let (_, status) = self.step(&mut [])?;
+            match self.state {
+                MemberState::Done => return Ok(None),
+                MemberState::End(_) => break,
+                _ if self.data.member_position() >= HEADER_SIZE as u64 => break,
+                _ if status == Status::MemNeeded && self.eof => {
+                    return Err(self.error(self.data.unexpected_eof()))
+                }
+                _ => {}
+            }
+        }
+        let header = self.data.header().expect("header of a started member");
+        Ok(Some(MemberReader {
+            member_offset: self.data.member_offset(),
+            members: self,
+            header,
+        }))
+    }
+
+    /// Runs the decompressor once, updating the state for the member
+    /// boundaries it reports.
+    fn step(&mut self, buf: &mut [u8]) -> io::Result<(usize, Status)> {
+        let (read, ret) = decompress_step(&mut self.obj, &mut self.data, &mut self.eof, buf)?;
+
+        let status = match ret {
+            Ok(Status::MemberEnd) => {
+                self.members += 1;
+                self.state = MemberState::End(Trailer {
+                    data_crc: self.data.data_crc(),
+                    data_size: self.data.data_position(),
+                    member_size: self.data.member_position(),
+                });
+                Status::MemberEnd
+            }
+            Ok(Status::StreamEnd) => {
+                self.state = MemberState::Done;
+                Status::StreamEnd
+            }
+            Ok(status) => {
+                self.state = MemberState::Data;
+                status
+            }
+            // Like lzip, ignore trailing data after the last member.
+            Err(Error::DataMagic) if self.members > 0 => {
+                self.state = MemberState::Done;
+                Status::StreamEnd
+            }
+            Err(e) => return Err(self.error(e)),
+        };
+        Ok((read, status))
+    }
+
+    fn error(&self, e: Error) -> io::Error {
+        decode_error(&self.data, self.members, e)
+    }
+}
+
+impl<R> Members<R> {
+    /// Acquires a reference to the underlying reader.
+    pub fn get_ref(&self) -> &R {
+        &self.obj
+    }
+
+    /// Acquires a mutable reference to the underlying stream.
+    ///
+    /// Note that mutation of the stream may result in surprising results if
+    /// this reader is continued to be used.
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.obj
+    }
+
+    /// Consumes this reader, returning the underlying reader.
+    pub fn into_inner(self) -> R {
+        self.obj
+    }
+}
+
+impl<'a, R> MemberReader<'a, R> {
+    /// Returns the header of this member.
+    pub fn header(&self) -> Header {
+        self.header
+    }
+
+    /// Returns the offset of this member in the compressed stream.
+    pub fn member_offset(&self) -> u64 {
+        self.member_offset
+    }
+
+    /// Returns the trailer of this member once all of its data has been
+    /// read, or `None` before that.
+    ///
+    /// The trailer has already been checked against the decompressed data,
+    /// reading fails if it doesn't match.
+    pub fn trailer(&self) -> Option<Trailer> {
+        match self.members.state {
+            MemberState::End(trailer) => Some(trailer),
+            _ => None,
+        }
+    }
+}
+
+impl<'a, R: BufRead> Read for MemberReader<'a, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            if !matches!(self.members.state, MemberState::Data) {
+                return Ok(0);
+            }
+            let (read, status) = self.members.step(buf)?;
+            if status == Status::MemNeeded && self.members.eof && read == 0 && !buf.is_empty() {
+                return Err(self.members.error(self.members.data.unexpected_eof()));
+            }
+            if read > 0 || buf.is_empty() {
+                return Ok(read);
+            }
+        }
+    }
+}
@@ -0,0 +1,108 @@
+//! Carving of lzip members out of arbitrary data, such as disk images.
+
+use std::io;
+use std::io::prelude::*;
+use std::io::{BufReader, SeekFrom};
+
+use bufread::decompress_step;
+use format::{Header, HEADER_SIZE, MAGIC};
+use index::Member;
+use mem::{Decompress, Status};
+
+/// Searches the data read from `r` for lzip members, returning those that
+/// decode correctly in the order they appear.
+///
+/// Every valid member header found is taken as a candidate, and the member
+/// starting there is decoded and checked against its trailer. Members found
+/// this way can't overlap, so scanning resumes after the end of each one;
+/// after a candidate that fails to decode it resumes at the next byte.
+///
+/// The `data_offset` of each member is where its data would start if the
+/// members found were joined into a single file.
+///
+/// # Example
+///
+/// ```no_run
+/// use std::fs::File;
+///
+/// let mut image = File::open("disk.img").unwrap();
+/// for member in lzip::carve(&mut image).unwrap() {
+///     println!("{} bytes at {}", member.member_size, member.member_offset);
+/// }
+/// ```
+pub fn carve<R: Read + Seek>(r: &mut R) -> io::Result<Vec<Member>> {
+    let mut members = Vec::new();
+    let mut data_offset = 0;
+    let mut buf = vec![0; 64 * 1024];
+    let mut pos = 0;
+    loop {
+        r.seek(SeekFrom::Start(pos))?;
+        let n = fill(r, &mut buf)?;
+        if n < HEADER_SIZE {
+            return Ok(members);
+        }
+        let found = buf[..n]
+            .windows(HEADER_SIZE)
+            .position(|w| w[..4] == MAGIC && Header::parse(w).is_ok());
+        let offset = match found {
+            Some(i) => pos + i as u64,
+            None if n < buf.len() => return Ok(members),
+            // A header may straddle the end of the buffer.
+            None => {
+                pos += (n - HEADER_SIZE + 1) as u64;
+                continue;
+            }
+        };
+        match decode_member(r, offset)? {
+            Some(mut member) => {
+                member.data_offset = data_offset;
+                data_offset += member.data_size;
+                pos = offset + member.member_size;
+                members.push(member);
+            }
+            None => pos = offset + 1,
+        }
+    }
+}
+
+/// Decodes the member starting at `offset`, returning its location if it
+/// decodes correctly.
+fn decode_member<R: Read + Seek>(r: &mut R, offset: u64) -> io::Result<Option<Member>> {
+    r.seek(SeekFrom::Start(offset))?;
+    let mut r = BufReader::new(r);
+    let mut data = Decompress::new();
+    let mut out = vec![0; 64 * 1024];
+    let mut eof = false;
+    loop {
+        match decompress_step(&mut r, &mut data, &mut eof, &mut out)?.1 {
+            Ok(Status::MemberEnd) => {
+                return Ok(Some(Member {
+                    member_offset: offset,
+                    member_size: data.member_position(),
+                    data_offset: 0,
+                    data_size: data.data_position(),
+                    data_crc: data.data_crc(),
+                    dictionary_size: data.dictionary_size(),
+                }))
+            }
+            Ok(Status::StreamEnd) | Err(_) => return Ok(None),
+            Ok(Status::MemNeeded) if eof => return Ok(None),
+            Ok(_) => {}
+        }
+    }
+}
+
+/// Reads from `r` until `buf` is full or the end of the input is reached,
+/// returning the number of bytes read.
+fn fill<R: Read>(r: &mut R, buf: &mut [u8]) -> io::Result<usize> {
+    let mut n = 0;
+    while n < buf.len() {
+        match r.read(&mut buf[n..]) {
+            Ok(0) => break,
+            Ok(m) => n += m,
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(n)
+}
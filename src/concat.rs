@@ -0,0 +1,124 @@
+//! Concatenation of lzip files without recompression.
+
+use std::error;
+use std::fmt;
+use std::io;
+use std::io::prelude::*;
+use std::io::SeekFrom;
+
+use index::{self, Index};
+use mem::DecodeError;
+use verify;
+
+/// Problems found in an input by `concat`.
+#[derive(PartialEq, Eq, Copy, Debug, Clone)]
+pub enum ConcatError {
+    /// The member headers or trailers of an input are inconsistent.
+    Invalid {
+        /// Position of the input in the list given to `concat`.
+        input: usize,
+        /// What is wrong with the input.
+        error: index::Error,
+    },
+
+    /// An input has data after its last member.
+    TrailingData {
+        /// Position of the input in the list given to `concat`.
+        input: usize,
+        /// Number of bytes of trailing data.
+        size: u64,
+    },
+
+    /// The compressed data of an input is damaged.
+    Damaged {
+        /// Position of the input in the list given to `concat`.
+        input: usize,
+        /// The error found while testing the input.
+        error: DecodeError,
+    },
+}
+
+/// Concatenates the lzip files `inputs` into `output`, returning the number
+/// of bytes written.
+///
+/// lzip files are concatenable, so the members of every input are copied
+/// byte for byte without being recompressed. All inputs are indexed and
+/// tested first, and nothing is written if any of them is damaged or has
+/// trailing data; such inputs are reported with a `ConcatError` wrapped in
+/// the `io::Error`.
+///
+/// # Example
+///
+/// ```no_run
+/// use std::fs::File;
+///
+/// let mut inputs = vec![File::open("00.lz").unwrap(), File::open("01.lz").unwrap()];
+/// let mut output = File::create("day.lz").unwrap();
+/// lzip::concat(&mut inputs, &mut output).unwrap();
+/// ```
+pub fn concat<R, W>(inputs: &mut [R], output: &mut W) -> io::Result<u64>
+where
+    R: Read + Seek,
+    W: Write + ?Sized,
+{
+    let mut sizes = Vec::with_capacity(inputs.len());
+    for (input, r) in inputs.iter_mut().enumerate() {
+        let index = Index::new(r).map_err(|e| match index_error(&e) {
+            Some(error) => ConcatError::Invalid { input, error }.into(),
+            None => e,
+        })?;
+        if index.trailing_size() > 0 {
+            let size = index.trailing_size();
+            return Err(ConcatError::TrailingData { input, size }.into());
+        }
+
+        r.seek(SeekFrom::Start(0))?;
+        let report = verify::test(&mut *r)?;
+        if let Some(error) = report.error {
+            return Err(ConcatError::Damaged { input, error }.into());
+        }
+        sizes.push(index.compressed_size());
+    }
+
+    let mut total = 0;
+    for (r, size) in inputs.iter_mut().zip(sizes) {
+        r.seek(SeekFrom::Start(0))?;
+        let copied = io::copy(&mut r.take(size), output)?;
+        if copied != size {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "input file shrank while being copied",
+            ));
+        }
+        total += copied;
+    }
+    Ok(total)
+}
+
+fn index_error(e: &io::Error) -> Option<index::Error> {
+    e.get_ref()
+        .and_then(|e| e.downcast_ref::<index::Error>())
+        .cloned()
+}
+
+impl error::Error for ConcatError {}
+
+impl fmt::Display for ConcatError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConcatError::Invalid { input, error } => write!(f, "input {}: {}", input, error),
+            ConcatError::TrailingData { input, size } => write!(
+                f,
+                "input {}: lzip: {} bytes of trailing data after the last member",
+                input, size
+            ),
+            ConcatError::Damaged { input, error } => write!(f, "input {}: {}", input, error),
+        }
+    }
+}
+
+impl From<ConcatError> for io::Error {
+    fn from(data: ConcatError) -> io::Error {
+        io::Error::new(io::ErrorKind::InvalidData, data)
+    }
+}
@@ -0,0 +1,288 @@
+//! CRC32 as used in lzip member trailers.
+
+use std::io;
+use std::io::prelude::*;
+
+/// Reversed CRC32 polynomial, as used by lzip, gzip and zlib.
+const POLY: u32 = 0xEDB8_8320;
+
+static TABLE: [u32; 256] = make_table();
+
+/// `X2N_TABLE[k]` is x^(2^k) modulo the CRC polynomial.
+static X2N_TABLE: [u32; 32] = make_x2n_table();
+
+/// An incremental CRC32 hasher computing the checksum stored in lzip
+/// trailers.
+///
+/// # Example
+///
+/// ```
+/// use lzip::Crc32;
+///
+/// let mut crc = Crc32::new();
+/// crc.update(b"12345");
+/// let mut tail = Crc32::new();
+/// tail.update(b"6789");
+/// crc.combine(&tail);
+/// assert_eq!(crc.sum(), 0xCBF4_3926);
+/// assert_eq!(crc.amount(), 9);
+/// ```
+#[derive(PartialEq, Eq, Copy, Debug, Clone)]
+pub struct Crc32 {
+    crc: u32,
+    amount: u64,
+}
+
+impl Crc32 {
+    /// Creates a new hasher with no data hashed yet.
+    pub fn new() -> Crc32 {
+        Crc32 { crc: 0, amount: 0 }
+    }
+
+    /// Creates a hasher resuming from the checksum `crc` of `amount` bytes.
+    pub fn from_value(crc: u32, amount: u64) -> Crc32 {
+        Crc32 { crc, amount }
+    }
+
+    /// Hashes `buf`, continuing from the data hashed so far.
+    ///
+    /// Carry-less multiplication (PCLMULQDQ) on x86 and the CRC32
+    /// instructions on AArch64 are used when the CPU supports them, with a
+    /// portable table-driven implementation as fallback.
+    pub fn update(&mut self, buf: &[u8]) {
+        self.crc = update(self.crc, buf);
+        self.amount += buf.len() as u64;
+    }
+
+    /// Returns the checksum of the data hashed so far.
+    pub fn sum(&self) -> u32 {
+        self.crc
+    }
+
+    /// Returns the number of bytes hashed so far.
+    pub fn amount(&self) -> u64 {
+        self.amount
+    }
+
+    /// Resets this hasher to its initial state.
+    pub fn reset(&mut self) {
+        *self = Crc32::new();
+    }
+
+    /// Extends this checksum as if the data hashed by `other` had been
+    /// hashed by this hasher as well.
+    ///
+    /// This takes time logarithmic in the amount of data hashed by `other`,
+    /// which allows checksums of separately hashed blocks to be joined.
+    pub fn combine(&mut self, other: &Crc32) {
+        self.crc = multmodp(x2nmodp(other.amount, 3), self.crc) ^ other.crc;
+        self.amount += other.amount;
+    }
+}
+
+impl Default for Crc32 {
+    fn default() -> Crc32 {
+        Crc32::new()
+    }
+}
+
+impl Write for Crc32 {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.update(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Returns the checksum `crc` extended with `buf`, using the fastest
+/// implementation the CPU supports.
+fn update(crc: u32, buf: &[u8]) -> u32 {
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    {
+        if buf.len() >= pclmulqdq::MIN_SIZE
+            && is_x86_feature_detected!("pclmulqdq")
+            && is_x86_feature_detected!("sse4.1")
+        {
+            return unsafe { pclmulqdq::update(crc, buf) };
+        }
+    }
+    #[cfg(target_arch = "aarch64")]
+    {
+        if std::arch::is_aarch64_feature_detected!("crc") {
+            return unsafe { aarch64::update(crc, buf) };
+        }
+    }
+    update_portable(crc, buf)
+}
+
+fn update_portable(crc: u32, buf: &[u8]) -> u32 {
+    let mut crc = !crc;
+    for &b in buf {
+        crc = TABLE[((crc ^ b as u32) & 0xFF) as usize] ^ (crc >> 8);
+    }
+    !crc
+}
+
+/// CRC32 by folding with carry-less multiplication, following Intel's "Fast
+/// CRC Computation for Generic Polynomials Using PCLMULQDQ Instruction".
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+mod pclmulqdq {
+    #[cfg(target_arch = "x86")]
+    use std::arch::x86::*;
+    #[cfg(target_arch = "x86_64")]
+    use std::arch::x86_64::*;
+
+    /// Below this size the setup costs more than the table lookups.
+    pub const MIN_SIZE: usize = 128;
+
+    // Folding constants for the reversed polynomial: x^(4*128+32) mod P and
+    // x^(4*128-32) mod P to fold by four, x^(128+32) mod P and x^(128-32)
+    // mod P to fold by one, x^64 mod P, then P and floor(x^64 / P) for the
+    // final Barrett reduction. All are bit-reflected and shifted left by one.
+    const K1: i64 = 0x1_5444_2bd4;
+    const K2: i64 = 0x1_c6e4_1596;
+    const K3: i64 = 0x1_7519_97d0;
+    const K4: i64 = 0x0_ccaa_009e;
+    const K5: i64 = 0x1_63cd_6124;
+    const P_X: i64 = 0x1_db71_0641;
+    const U_PRIME: i64 = 0x1_f701_1641;
+
+    #[target_feature(enable = "pclmulqdq", enable = "sse2", enable = "sse4.1")]
+    pub unsafe fn update(crc: u32, mut buf: &[u8]) -> u32 {
+        debug_assert!(buf.len() >= MIN_SIZE);
+        let mut x3 = load(&mut buf);
+        let mut x2 = load(&mut buf);
+        let mut x1 = load(&mut buf);
+        let mut x0 = load(&mut buf);
+        x3 = _mm_xor_si128(x3, _mm_cvtsi32_si128(!crc as i32));
+
+        let k1k2 = _mm_set_epi64x(K2, K1);
+        while buf.len() >= 64 {
+            x3 = fold(x3, load(&mut buf), k1k2);
+            x2 = fold(x2, load(&mut buf), k1k2);
+            x1 = fold(x1, load(&mut buf), k1k2);
+            x0 = fold(x0, load(&mut buf), k1k2);
+        }
+
+        let k3k4 = _mm_set_epi64x(K4, K3);
+        let mut x = fold(x3, x2, k3k4);
+        x = fold(x, x1, k3k4);
+        x = fold(x, x0, k3k4);
+        while buf.len() >= 16 {
+            x = fold(x, load(&mut buf), k3k4);
+        }
+
+        // Reduce 128 bits to 64.
+        let low32 = _mm_set_epi32(0, 0, 0, !0);
+        let x = _mm_xor_si128(_mm_clmulepi64_si128(x, k3k4, 0x10), _mm_srli_si128(x, 8));
+        let x = _mm_xor_si128(
+            _mm_clmulepi64_si128(_mm_and_si128(x, low32), _mm_set_epi64x(0, K5), 0x00),
+            _mm_srli_si128(x, 4),
+        );
+
+        // Barrett reduction to 32 bits.
+        let pu = _mm_set_epi64x(U_PRIME, P_X);
+        let t1 = _mm_clmulepi64_si128(_mm_and_si128(x, low32), pu, 0x10);
+        let t2 = _mm_clmulepi64_si128(_mm_and_si128(t1, low32), pu, 0x00);
+        let crc = !(_mm_extract_epi32(_mm_xor_si128(x, t2), 1) as u32);
+
+        super::update_portable(crc, buf)
+    }
+
+    #[inline]
+    #[target_feature(enable = "pclmulqdq", enable = "sse2")]
+    unsafe fn fold(a: __m128i, b: __m128i, keys: __m128i) -> __m128i {
+        let t1 = _mm_clmulepi64_si128(a, keys, 0x00);
+        let t2 = _mm_clmulepi64_si128(a, keys, 0x11);
+        _mm_xor_si128(_mm_xor_si128(b, t1), t2)
+    }
+
+    #[inline]
+    #[target_feature(enable = "sse2")]
+    unsafe fn load(buf: &mut &[u8]) -> __m128i {
+        let r = _mm_loadu_si128(buf.as_ptr() as *const __m128i);
+        *buf = &buf[16..];
+        r
+    }
+}
+
+/// CRC32 using the ARMv8 CRC32 instructions, which implement the same
+/// polynomial as lzip.
+#[cfg(target_arch = "aarch64")]
+mod aarch64 {
+    use std::arch::aarch64::{__crc32b, __crc32d};
+
+    #[target_feature(enable = "crc")]
+    pub unsafe fn update(crc: u32, buf: &[u8]) -> u32 {
+        let mut crc = !crc;
+        let mut chunks = buf.chunks_exact(8);
+        for chunk in &mut chunks {
+            let mut word = [0; 8];
+            word.copy_from_slice(chunk);
+            crc = __crc32d(crc, u64::from_le_bytes(word));
+        }
+        for &b in chunks.remainder() {
+            crc = __crc32b(crc, b);
+        }
+        !crc
+    }
+}
+
+const fn make_table() -> [u32; 256] {
+    let mut table = [0; 256];
+    let mut n = 0;
+    while n < 256 {
+        let mut c = n as u32;
+        let mut k = 0;
+        while k < 8 {
+            c = if c & 1 != 0 { POLY ^ (c >> 1) } else { c >> 1 };
+            k += 1;
+        }
+        table[n] = c;
+        n += 1;
+    }
+    table
+}
+
+const fn make_x2n_table() -> [u32; 32] {
+    let mut table = [0; 32];
+    // x^1, with the coefficient of x^0 in the most significant bit.
+    let mut p = 1 << 30;
+    let mut n = 0;
+    while n < 32 {
+        table[n] = p;
+        p = multmodp(p, p);
+        n += 1;
+    }
+    table
+}
+
+/// Multiplies `a` and `b` modulo the CRC polynomial.
+const fn multmodp(a: u32, mut b: u32) -> u32 {
+    let mut m = 1 << 31;
+    let mut p = 0;
+    while m != 0 {
+        if a & m != 0 {
+            p ^= b;
+        }
+        m >>= 1;
+        b = if b & 1 != 0 { (b >> 1) ^ POLY } else { b >> 1 };
+    }
+    p
+}
+
+/// Returns x^(n * 2^k) modulo the CRC polynomial.
+fn x2nmodp(mut n: u64, mut k: usize) -> u32 {
+    let mut p = 1 << 31;
+    while n != 0 {
+        if n & 1 != 0 {
+            p = multmodp(X2N_TABLE[k & 31], p);
+        }
+        n >>= 1;
+        k += 1;
+    }
+    p
+}
@@ -0,0 +1,222 @@
+//! Extraction and removal of members in the style of `lziprecover --dump`,
+//! `--strip` and `--remove`.
+
+use std::fs::File;
+use std::io;
+use std::io::prelude::*;
+use std::io::SeekFrom;
+use std::ops::Range;
+
+use index::Index;
+use verify;
+
+/// A selection of members and trailing data of an lzip file, for `dump`,
+/// `strip` and `remove`.
+///
+/// # Example
+///
+/// ```
+/// use lzip::Selection;
+///
+/// // The third member, any damaged members and the trailing data.
+/// let selection = Selection::new().member(2).damaged().trailing_data();
+/// ```
+#[derive(PartialEq, Eq, Debug, Clone, Default)]
+pub struct Selection {
+    members: Vec<usize>,
+    damaged: bool,
+    empty: bool,
+    trailing_data: bool,
+}
+
+impl Selection {
+    /// Creates an empty selection.
+    pub fn new() -> Selection {
+        Selection::default()
+    }
+
+    /// Selects member number `n`, counting from zero.
+    ///
+    /// Using the selection on a file with no such member is an error.
+    pub fn member(mut self, n: usize) -> Selection {
+        self.members.push(n);
+        self
+    }
+
+    /// Selects the members that fail to decompress or whose data doesn't
+    /// match their trailer.
+    ///
+    /// Finding these requires decompressing every member.
+    pub fn damaged(mut self) -> Selection {
+        self.damaged = true;
+        self
+    }
+
+    /// Selects the members with no data.
+    pub fn empty(mut self) -> Selection {
+        self.empty = true;
+        self
+    }
+
+    /// Selects the data following the last member.
+    pub fn trailing_data(mut self) -> Selection {
+        self.trailing_data = true;
+        self
+    }
+
+    /// Returns the ranges of the file selected and of those not selected, in
+    /// the order they appear, along with the number of members not selected.
+    fn ranges<R: Read + Seek>(&self, r: &mut R) -> io::Result<Ranges> {
+        let index = Index::new(r)?;
+        let count = index.members().len();
+        if let Some(n) = self.members.iter().find(|&&n| n >= count) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("lzip: member {} doesn't exist; the file has {}", n, count),
+            ));
+        }
+        let mut selected = Vec::new();
+        let mut rest = Vec::new();
+        for (i, member) in index.members().iter().enumerate() {
+            let mut chosen = self.members.contains(&i) || (self.empty && member.data_size == 0);
+            if !chosen && self.damaged {
+                r.seek(SeekFrom::Start(member.member_offset))?;
+                let report = verify::test(r.take(member.member_size))?;
+                chosen = !report.is_ok() || report.members.len() != 1;
+            }
+            let range = member.member_offset..member.member_offset + member.member_size;
+            if chosen {
+                selected.push(range);
+            } else {
+                rest.push(range);
+            }
+        }
+        let kept_members = rest.len();
+        let trailing = index.compressed_size()..index.file_size();
+        if !trailing.is_empty() {
+            if self.trailing_data {
+                selected.push(trailing);
+            } else {
+                rest.push(trailing);
+            }
+        }
+        Ok(Ranges {
+            selected,
+            rest,
+            kept_members,
+        })
+    }
+}
+
+struct Ranges {
+    selected: Vec<Range<u64>>,
+    rest: Vec<Range<u64>>,
+    kept_members: usize,
+}
+
+/// Copies the parts of the lzip file read from `r` chosen by `selection` to
+/// `output`, like `lziprecover --dump`, returning the number of bytes
+/// written.
+///
+/// The members and trailing data are copied byte for byte, in the order they
+/// appear in the file, so selecting only members produces a valid lzip file.
+///
+/// # Example
+///
+/// ```no_run
+/// use std::fs::File;
+/// use lzip::Selection;
+///
+/// let mut file = File::open("upload.lz").unwrap();
+/// let mut garbage = File::create("garbage.bin").unwrap();
+/// lzip::dump(&mut file, &Selection::new().trailing_data(), &mut garbage).unwrap();
+/// ```
+pub fn dump<R, W>(r: &mut R, selection: &Selection, output: &mut W) -> io::Result<u64>
+where
+    R: Read + Seek,
+    W: Write + ?Sized,
+{
+    let ranges = selection.ranges(r)?;
+    copy_ranges(r, &ranges.selected, output)
+}
+
+/// Copies the lzip file read from `r` to `output` without the parts chosen
+/// by `selection`, like `lziprecover --strip`, returning the number of bytes
+/// written.
+///
+/// This fails without writing anything if every member would be removed.
+pub fn strip<R, W>(r: &mut R, selection: &Selection, output: &mut W) -> io::Result<u64>
+where
+    R: Read + Seek,
+    W: Write + ?Sized,
+{
+    let rest = kept_ranges(r, selection)?;
+    copy_ranges(r, &rest, output)
+}
+
+/// Removes the parts of the lzip file `file` chosen by `selection` in
+/// place, like `lziprecover --remove`, returning the new size of the file.
+///
+/// `file` must be open for both reading and writing. This fails without
+/// modifying the file if every member would be removed.
+///
+/// # Example
+///
+/// ```no_run
+/// use std::fs::OpenOptions;
+/// use lzip::Selection;
+///
+/// let mut file = OpenOptions::new().read(true).write(true).open("archive.lz").unwrap();
+/// lzip::remove(&mut file, &Selection::new().damaged()).unwrap();
+/// ```
+pub fn remove(file: &mut File, selection: &Selection) -> io::Result<u64> {
+    let rest = kept_ranges(file, selection)?;
+    // Every kept range moves towards the start of the file, so copying
+    // forwards never overwrites data not yet moved.
+    let mut buf = vec![0; 64 * 1024];
+    let mut end = 0;
+    for range in rest {
+        if range.start == end {
+            end = range.end;
+            continue;
+        }
+        let mut pos = range.start;
+        while pos < range.end {
+            let n = buf.len().min((range.end - pos) as usize);
+            file.seek(SeekFrom::Start(pos))?;
+            file.read_exact(&mut buf[..n])?;
+            file.seek(SeekFrom::Start(end))?;
+            file.write_all(&buf[..n])?;
+            pos += n as u64;
+            end += n as u64;
+        }
+    }
+    file.set_len(end)?;
+    Ok(end)
+}
+
+/// Returns the ranges of the file not chosen by `selection`, failing if no
+/// member is left.
+fn kept_ranges<R: Read + Seek>(r: &mut R, selection: &Selection) -> io::Result<Vec<Range<u64>>> {
+    let ranges = selection.ranges(r)?;
+    if ranges.kept_members == 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "lzip: can't remove all members",
+        ));
+    }
+    Ok(ranges.rest)
+}
+
+fn copy_ranges<R, W>(r: &mut R, ranges: &[Range<u64>], output: &mut W) -> io::Result<u64>
+where
+    R: Read + Seek,
+    W: Write + ?Sized,
+{
+    let mut total = 0;
+    for range in ranges {
+        r.seek(SeekFrom::Start(range.start))?;
+        total += io::copy(&mut r.take(range.end - range.start), output)?;
+    }
+    Ok(total)
+}
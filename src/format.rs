@@ -0,0 +1,361 @@
+//! Pure Rust handling of the lzip member format.
+//!
+//! An lzip file is a sequence of members, each made of a 6-byte header, the
+//! LZMA stream and a 20-byte trailer. The types in this module read and write
+//! these framing structures without going through lzlib.
+
+use std::error;
+use std::fmt;
+use std::io;
+use std::io::prelude::*;
+
+/// Size in bytes of a member header.
+pub const HEADER_SIZE: usize = 6;
+
+/// Size in bytes of a member trailer.
+pub const TRAILER_SIZE: usize = 20;
+
+/// Size in bytes of the smallest possible member.
+pub const MIN_MEMBER_SIZE: u64 = 36;
+
+/// Magic bytes every member header starts with.
+pub const MAGIC: [u8; 4] = *b"LZIP";
+
+/// The only version of the member format in use.
+pub const VERSION: u8 = 1;
+
+/// Smallest dictionary size a header may declare.
+pub const MIN_DICTIONARY_SIZE: u32 = 1 << 12;
+
+/// Largest dictionary size a header may declare.
+pub const MAX_DICTIONARY_SIZE: u32 = 1 << 29;
+
+/// The header found at the start of every lzip member.
+///
+/// # Example
+///
+/// ```
+/// use lzip::format::Header;
+///
+/// let header = Header::new(1 << 20).unwrap();
+/// let bytes = header.to_bytes();
+/// assert_eq!(&bytes, b"LZIP\x01\x14");
+/// assert_eq!(Header::parse(&bytes).unwrap().dictionary_size(), 1 << 20);
+/// ```
+#[derive(PartialEq, Eq, Copy, Debug, Clone)]
+pub struct Header {
+    version: u8,
+    coded_dictionary_size: u8,
+}
+
+/// The trailer found at the end of every lzip member.
+///
+/// The trailer records the CRC32 and size of the decompressed data, and the
+/// size of the whole member including header and trailer.
+#[derive(PartialEq, Eq, Copy, Debug, Clone)]
+pub struct Trailer {
+    /// CRC32 of the decompressed data.
+    pub data_crc: u32,
+    /// Size of the decompressed data.
+    pub data_size: u64,
+    /// Size of the member, including header and trailer.
+    pub member_size: u64,
+}
+
+/// Errors encountered when parsing or validating lzip framing structures.
+#[derive(PartialEq, Eq, Copy, Debug, Clone)]
+pub enum Error {
+    /// The data ended before a complete structure could be read.
+    Truncated,
+
+    /// The data does not start with the "LZIP" magic bytes.
+    BadMagic,
+
+    /// The header declares a version of the format that isn't supported.
+    UnsupportedVersion(u8),
+
+    /// The header codes a dictionary size outside of the valid range.
+    InvalidDictionarySize(u32),
+
+    /// The CRC stored in a trailer doesn't match the decompressed data.
+    CrcMismatch {
+        /// CRC stored in the trailer.
+        stored: u32,
+        /// CRC of the decompressed data.
+        computed: u32,
+    },
+
+    /// The data size stored in a trailer doesn't match the decompressed data.
+    DataSizeMismatch {
+        /// Data size stored in the trailer.
+        stored: u64,
+        /// Size of the decompressed data.
+        computed: u64,
+    },
+
+    /// The member size stored in a trailer doesn't match the member.
+    MemberSizeMismatch {
+        /// Member size stored in the trailer.
+        stored: u64,
+        /// Actual size of the member.
+        computed: u64,
+    },
+}
+
+impl Header {
+    /// Creates a header declaring a dictionary of at least `dictionary_size`
+    /// bytes.
+    ///
+    /// The header can only code some sizes, so the declared dictionary size
+    /// is rounded up to the next one that can be represented.
+    pub fn new(dictionary_size: u32) -> Result<Header, Error> {
+        if !(MIN_DICTIONARY_SIZE..=MAX_DICTIONARY_SIZE).contains(&dictionary_size) {
+            return Err(Error::InvalidDictionarySize(dictionary_size));
+        }
+        let mut coded = (32 - (dictionary_size - 1).leading_zeros()) as u8;
+        if dictionary_size > MIN_DICTIONARY_SIZE {
+            let base = 1u32 << coded;
+            let fraction = base / 16;
+            for i in (1..8).rev() {
+                if base - i * fraction >= dictionary_size {
+                    coded |= (i as u8) << 5;
+                    break;
+                }
+            }
+        }
+        Ok(Header {
+            version: VERSION,
+            coded_dictionary_size: coded,
+        })
+    }
+
+    /// Parses a header from the start of `buf`.
+    pub fn parse(buf: &[u8]) -> Result<Header, Error> {
+        if buf.len() < HEADER_SIZE {
+            return Err(Error::Truncated);
+        }
+        if buf[..4] != MAGIC {
+            return Err(Error::BadMagic);
+        }
+        if buf[4] != VERSION {
+            return Err(Error::UnsupportedVersion(buf[4]));
+        }
+        let header = Header {
+            version: VERSION,
+            coded_dictionary_size: buf[5],
+        };
+        let size = header.dictionary_size();
+        if !(MIN_DICTIONARY_SIZE..=MAX_DICTIONARY_SIZE).contains(&size) {
+            return Err(Error::InvalidDictionarySize(size));
+        }
+        Ok(header)
+    }
+
+    /// Reads and parses a header from `r`.
+    pub fn read<R: Read>(r: &mut R) -> io::Result<Header> {
+        let mut buf = [0; HEADER_SIZE];
+        r.read_exact(&mut buf)?;
+        Ok(Header::parse(&buf)?)
+    }
+
+    /// Returns the version of the member format.
+    ///
+    /// This is always `VERSION` for headers returned by `parse`, but `detect`
+    /// also recognises headers of unsupported versions.
+    pub fn version(&self) -> u8 {
+        self.version
+    }
+
+    /// Returns true if this header has a supported version and declares a
+    /// valid dictionary size, as all headers returned by `parse` do.
+    pub fn is_valid(&self) -> bool {
+        self.version == VERSION
+            && (MIN_DICTIONARY_SIZE..=MAX_DICTIONARY_SIZE).contains(&self.dictionary_size())
+    }
+
+    /// Returns the dictionary size declared by this header.
+    pub fn dictionary_size(&self) -> u32 {
+        let coded = self.coded_dictionary_size;
+        let mut size = 1u32 << (coded & 0x1F);
+        if size > MIN_DICTIONARY_SIZE {
+            size -= (size / 16) * ((coded >> 5) & 7) as u32;
+        }
+        size
+    }
+
+    /// Returns the encoded form of this header.
+    pub fn to_bytes(&self) -> [u8; HEADER_SIZE] {
+        let mut buf = [0; HEADER_SIZE];
+        buf[..4].copy_from_slice(&MAGIC);
+        buf[4] = self.version;
+        buf[5] = self.coded_dictionary_size;
+        buf
+    }
+
+    /// Writes the encoded form of this header to `w`.
+    pub fn write<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        w.write_all(&self.to_bytes())
+    }
+}
+
+/// Returns true if `prefix` starts with a valid lzip member header.
+///
+/// ```
+/// assert!(lzip::format::is_lzip(b"LZIP\x01\x0c"));
+/// assert!(!lzip::format::is_lzip(b"LZIP\x02\x0c"));
+/// assert!(!lzip::format::is_lzip(b"\x1f\x8b\x08"));
+/// ```
+pub fn is_lzip(prefix: &[u8]) -> bool {
+    Header::parse(prefix).is_ok()
+}
+
+/// Recognises the lzip member header at the start of `prefix`.
+///
+/// This returns `None` unless `prefix` holds at least a whole header
+/// starting with the magic bytes. Unlike `Header::parse`, headers of
+/// unsupported versions or with invalid dictionary sizes are returned as
+/// well, so that they can be told apart from data in other formats; check
+/// `Header::is_valid` and `Header::version` before decoding.
+///
+/// ```
+/// use lzip::format::{detect, VERSION};
+///
+/// let header = detect(b"LZIP\x02\x0c").unwrap();
+/// assert_eq!(header.version(), 2);
+/// assert!(!header.is_valid());
+/// assert!(detect(b"PK\x03\x04\x14\x00").is_none());
+/// ```
+pub fn detect(prefix: &[u8]) -> Option<Header> {
+    if prefix.len() < HEADER_SIZE || prefix[..4] != MAGIC {
+        return None;
+    }
+    Some(Header {
+        version: prefix[4],
+        coded_dictionary_size: prefix[5],
+    })
+}
+
+impl Trailer {
+    /// Parses a trailer from the start of `buf`.
+    pub fn parse(buf: &[u8]) -> Result<Trailer, Error> {
+        if buf.len() < TRAILER_SIZE {
+            return Err(Error::Truncated);
+        }
+        let mut crc = [0; 4];
+        let mut data_size = [0; 8];
+        let mut member_size = [0; 8];
+        crc.copy_from_slice(&buf[0..4]);
+        data_size.copy_from_slice(&buf[4..12]);
+        member_size.copy_from_slice(&buf[12..20]);
+        Ok(Trailer {
+            data_crc: u32::from_le_bytes(crc),
+            data_size: u64::from_le_bytes(data_size),
+            member_size: u64::from_le_bytes(member_size),
+        })
+    }
+
+    /// Reads and parses a trailer from `r`.
+    pub fn read<R: Read>(r: &mut R) -> io::Result<Trailer> {
+        let mut buf = [0; TRAILER_SIZE];
+        r.read_exact(&mut buf)?;
+        Ok(Trailer::parse(&buf)?)
+    }
+
+    /// Returns the encoded form of this trailer.
+    pub fn to_bytes(&self) -> [u8; TRAILER_SIZE] {
+        let mut buf = [0; TRAILER_SIZE];
+        buf[0..4].copy_from_slice(&self.data_crc.to_le_bytes());
+        buf[4..12].copy_from_slice(&self.data_size.to_le_bytes());
+        buf[12..20].copy_from_slice(&self.member_size.to_le_bytes());
+        buf
+    }
+
+    /// Writes the encoded form of this trailer to `w`.
+    pub fn write<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        w.write_all(&self.to_bytes())
+    }
+
+    /// Checks that the fields of this trailer are consistent with each other.
+    ///
+    /// This catches most corrupt trailers without decoding the member: the
+    /// CRC and data size must both be zero or both be non-zero, and the data
+    /// and member sizes must be within the bounds the LZMA coding allows.
+    pub fn is_consistent(&self) -> bool {
+        let dsize = self.data_size;
+        let msize = self.member_size;
+        if (self.data_crc == 0) != (dsize == 0) || msize < MIN_MEMBER_SIZE {
+            return false;
+        }
+        let mlimit = (dsize.wrapping_mul(9).wrapping_add(7) / 8).wrapping_add(MIN_MEMBER_SIZE);
+        if mlimit > dsize && msize > mlimit {
+            return false;
+        }
+        let dlimit = (msize - 26).wrapping_mul(7090).wrapping_sub(1);
+        !(dlimit > msize && dsize > dlimit)
+    }
+
+    /// Checks this trailer against the values computed while decoding its
+    /// member.
+    ///
+    /// The CRC is checked first, then the data size and finally the member
+    /// size; the first mismatch found is returned.
+    pub fn validate(&self, data_crc: u32, data_size: u64, member_size: u64) -> Result<(), Error> {
+        if self.data_crc != data_crc {
+            return Err(Error::CrcMismatch {
+                stored: self.data_crc,
+                computed: data_crc,
+            });
+        }
+        if self.data_size != data_size {
+            return Err(Error::DataSizeMismatch {
+                stored: self.data_size,
+                computed: data_size,
+            });
+        }
+        if self.member_size != member_size {
+            return Err(Error::MemberSizeMismatch {
+                stored: self.member_size,
+                computed: member_size,
+            });
+        }
+        Ok(())
+    }
+}
+
+impl error::Error for Error {}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Truncated => f.write_str("lzip: truncated header or trailer"),
+            Error::BadMagic => f.write_str("lzip: bad magic number (file not in lzip format)"),
+            Error::UnsupportedVersion(v) => {
+                write!(f, "lzip: version {} member format not supported", v)
+            }
+            Error::InvalidDictionarySize(s) => {
+                write!(f, "lzip: invalid dictionary size {} in member header", s)
+            }
+            Error::CrcMismatch { stored, computed } => write!(
+                f,
+                "lzip: CRC mismatch; stored {:08X}, computed {:08X}",
+                stored, computed
+            ),
+            Error::DataSizeMismatch { stored, computed } => write!(
+                f,
+                "lzip: data size mismatch; stored {} (0x{:X}), computed {} (0x{:X})",
+                stored, stored, computed, computed
+            ),
+            Error::MemberSizeMismatch { stored, computed } => write!(
+                f,
+                "lzip: member size mismatch; stored {} (0x{:X}), computed {} (0x{:X})",
+                stored, stored, computed, computed
+            ),
+        }
+    }
+}
+
+impl From<Error> for io::Error {
+    fn from(data: Error) -> io::Error {
+        io::Error::new(io::ErrorKind::InvalidData, data)
+    }
+}
@@ -0,0 +1,324 @@
+//! Member index of seekable lzip files.
+//!
+//! An `Index` lists the position and size of every member of a file, both in
+//! the compressed file and in the decompressed data. It is built by walking
+//! the file backwards from trailer to trailer, so nothing is decompressed.
+
+use std::cmp;
+use std::error;
+use std::fmt;
+use std::io;
+use std::io::prelude::*;
+use std::io::SeekFrom;
+
+use format::{self, Header, Trailer, HEADER_SIZE, MIN_MEMBER_SIZE, TRAILER_SIZE};
+
+/// The location of a single member within an lzip file.
+#[derive(PartialEq, Eq, Copy, Debug, Clone)]
+pub struct Member {
+    /// Offset of the member in the compressed file.
+    pub member_offset: u64,
+    /// Size of the member in the compressed file, including header and
+    /// trailer.
+    pub member_size: u64,
+    /// Offset of the member's data in the decompressed data.
+    pub data_offset: u64,
+    /// Size of the member's decompressed data.
+    pub data_size: u64,
+    /// CRC32 of the member's decompressed data, as stored in its trailer.
+    pub data_crc: u32,
+    /// Dictionary size declared by the member header.
+    pub dictionary_size: u32,
+}
+
+/// An index of all the members of an lzip file.
+///
+/// # Example
+///
+/// ```no_run
+/// use std::fs::File;
+/// use lzip::index::Index;
+///
+/// let mut file = File::open("archive.lz").unwrap();
+/// let index = Index::new(&mut file).unwrap();
+/// for member in index.members() {
+///     println!("{} bytes at {}", member.data_size, member.member_offset);
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct Index {
+    members: Vec<Member>,
+    file_size: u64,
+}
+
+/// Inconsistencies found while building an `Index`.
+#[derive(PartialEq, Eq, Copy, Debug, Clone)]
+pub enum Error {
+    /// The input is smaller than the smallest possible member.
+    TooShort,
+
+    /// The member header at `pos` is invalid.
+    BadHeader {
+        /// Offset of the header in the file.
+        pos: u64,
+        /// What is wrong with the header.
+        error: format::Error,
+    },
+
+    /// The trailer at `pos` is inconsistent, or no valid trailer could be
+    /// found before trailing data ending at `pos`.
+    BadTrailer {
+        /// Offset of the trailer in the file.
+        pos: u64,
+    },
+
+    /// The last member starting at `pos` is truncated or corrupt.
+    TruncatedMember {
+        /// Offset of the member in the file.
+        pos: u64,
+    },
+
+    /// The data following the member ending at `pos` looks like a corrupt
+    /// member header rather than trailing data.
+    CorruptHeader {
+        /// Offset of the corrupt header in the file.
+        pos: u64,
+    },
+
+    /// The first `size` bytes of the file don't belong to any member.
+    Unindexed {
+        /// Number of bytes left over at the start of the file.
+        size: u64,
+    },
+}
+
+impl Index {
+    /// Builds the index of the lzip file read from `r`.
+    ///
+    /// Data following the last member is not an error; its size is reported
+    /// by `trailing_size`. Any other inconsistency in the member headers or
+    /// trailers makes this fail with an `Error` wrapped in the `io::Error`.
+    pub fn new<R: Read + Seek>(r: &mut R) -> io::Result<Index> {
+        let file_size = r.seek(SeekFrom::End(0))?;
+        if file_size < MIN_MEMBER_SIZE {
+            return Err(Error::TooShort.into());
+        }
+        let mut buf = [0; HEADER_SIZE];
+        read_at(r, 0, &mut buf)?;
+        Header::parse(&buf).map_err(|error| Error::BadHeader { pos: 0, error })?;
+
+        let mut members = Vec::new();
+        let mut pos = file_size;
+        let mut searched = false;
+        while pos >= MIN_MEMBER_SIZE {
+            let mut buf = [0; TRAILER_SIZE];
+            read_at(r, pos - TRAILER_SIZE as u64, &mut buf)?;
+            let trailer = Trailer::parse(&buf)?;
+            let member_size = trailer.member_size;
+            if member_size > pos || !trailer.is_consistent() {
+                if members.is_empty() && !searched {
+                    searched = true;
+                    pos = skip_trailing_data(r, pos)?;
+                    continue;
+                }
+                return Err(Error::BadTrailer {
+                    pos: pos - TRAILER_SIZE as u64,
+                }
+                .into());
+            }
+
+            let member_offset = pos - member_size;
+            let mut buf = [0; HEADER_SIZE];
+            read_at(r, member_offset, &mut buf)?;
+            let header = match Header::parse(&buf) {
+                Ok(header) => header,
+                Err(_) if members.is_empty() && !searched => {
+                    searched = true;
+                    pos = skip_trailing_data(r, pos)?;
+                    continue;
+                }
+                Err(error) => {
+                    return Err(Error::BadHeader {
+                        pos: member_offset,
+                        error,
+                    }
+                    .into())
+                }
+            };
+
+            members.push(Member {
+                member_offset,
+                member_size,
+                data_offset: 0,
+                data_size: trailer.data_size,
+                data_crc: trailer.data_crc,
+                dictionary_size: header.dictionary_size(),
+            });
+            pos = member_offset;
+        }
+        if pos != 0 {
+            return Err(Error::Unindexed { size: pos }.into());
+        }
+
+        members.reverse();
+        let mut data_offset = 0;
+        for member in &mut members {
+            member.data_offset = data_offset;
+            data_offset += member.data_size;
+        }
+        Ok(Index { members, file_size })
+    }
+
+    /// Returns the members of the file, in the order they appear.
+    pub fn members(&self) -> &[Member] {
+        &self.members
+    }
+
+    /// Returns the size of the whole file.
+    pub fn file_size(&self) -> u64 {
+        self.file_size
+    }
+
+    /// Returns the total size of the decompressed data.
+    pub fn data_size(&self) -> u64 {
+        self.members
+            .last()
+            .map_or(0, |m| m.data_offset + m.data_size)
+    }
+
+    /// Returns the size of all members, excluding trailing data.
+    pub fn compressed_size(&self) -> u64 {
+        self.members
+            .last()
+            .map_or(0, |m| m.member_offset + m.member_size)
+    }
+
+    /// Returns the number of bytes of trailing data after the last member.
+    pub fn trailing_size(&self) -> u64 {
+        self.file_size - self.compressed_size()
+    }
+
+    /// Returns the position in `members` of the member whose decompressed
+    /// data contains `data_offset`, if any.
+    pub fn find(&self, data_offset: u64) -> Option<usize> {
+        let i = self
+            .members
+            .partition_point(|m| m.data_offset + m.data_size <= data_offset);
+        if i < self.members.len() {
+            Some(i)
+        } else {
+            None
+        }
+    }
+}
+
+fn read_at<R: Read + Seek>(r: &mut R, pos: u64, buf: &mut [u8]) -> io::Result<()> {
+    r.seek(SeekFrom::Start(pos))?;
+    r.read_exact(buf)
+}
+
+/// Searches backwards from `pos` for the end of the last valid member,
+/// skipping any trailing data after it.
+fn skip_trailing_data<R: Read + Seek>(r: &mut R, pos: u64) -> io::Result<u64> {
+    const BLOCK_SIZE: u64 = 16384;
+    let mut buf = Vec::new();
+    // Candidate member ends are examined from `end` downwards. The buffer
+    // holds the bytes from `start` up to a few bytes past `end`, so that the
+    // start of the trailing data can be checked as well.
+    let mut end = pos;
+    while end >= MIN_MEMBER_SIZE {
+        let start = end.saturating_sub(BLOCK_SIZE);
+        let stop = cmp::min(end + HEADER_SIZE as u64, pos);
+        buf.resize((stop - start) as usize, 0);
+        read_at(r, start, &mut buf)?;
+
+        let lowest = cmp::max(start + TRAILER_SIZE as u64, MIN_MEMBER_SIZE);
+        let mut candidate = end;
+        while candidate >= lowest {
+            let i = (candidate - start) as usize;
+            let trailer = Trailer::parse(&buf[i - TRAILER_SIZE..i])?;
+            let member_size = trailer.member_size;
+            if member_size > candidate || !trailer.is_consistent() {
+                candidate -= 1;
+                continue;
+            }
+            let mut header = [0; HEADER_SIZE];
+            read_at(r, candidate - member_size, &mut header)?;
+            if Header::parse(&header).is_err() {
+                candidate -= 1;
+                continue;
+            }
+
+            let rest = &buf[i..];
+            if is_header_prefix(rest) {
+                return Err(Error::TruncatedMember { pos: candidate }.into());
+            }
+            if rest.len() >= HEADER_SIZE && is_corrupt_magic(rest) {
+                return Err(Error::CorruptHeader { pos: candidate }.into());
+            }
+            return Ok(candidate);
+        }
+        if start == 0 {
+            break;
+        }
+        end = lowest - 1;
+    }
+    Err(Error::BadTrailer {
+        pos: pos - TRAILER_SIZE as u64,
+    }
+    .into())
+}
+
+/// Returns true if `buf` could be the start of a member header. Anything
+/// else following the last member is trailing data, even if it starts with
+/// the magic bytes.
+fn is_header_prefix(buf: &[u8]) -> bool {
+    if buf.len() >= HEADER_SIZE {
+        return Header::parse(&buf[..HEADER_SIZE]).is_ok();
+    }
+    !buf.is_empty()
+        && buf.iter().zip(&format::MAGIC).all(|(a, b)| a == b)
+        && (buf.len() < 5 || buf[4] == format::VERSION)
+}
+
+/// Returns true if `buf` starts with something resembling the magic bytes,
+/// as a member header with a few damaged bytes would.
+fn is_corrupt_magic(buf: &[u8]) -> bool {
+    let matches = buf
+        .iter()
+        .zip(&format::MAGIC)
+        .filter(|&(a, b)| a == b)
+        .count();
+    matches > 1 && matches < 4
+}
+
+impl error::Error for Error {}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::TooShort => f.write_str("lzip: input file is too short"),
+            Error::BadHeader { pos, error } => write!(f, "{} at pos {}", error, pos),
+            Error::BadTrailer { pos } => write!(f, "lzip: bad trailer at pos {}", pos),
+            Error::TruncatedMember { pos } => write!(
+                f,
+                "lzip: last member in input file is truncated or corrupt (at pos {})",
+                pos
+            ),
+            Error::CorruptHeader { pos } => {
+                write!(f, "lzip: corrupt header in multimember file at pos {}", pos)
+            }
+            Error::Unindexed { size } => write!(
+                f,
+                "lzip: can't create file index; {} bytes at start of file belong to no member",
+                size
+            ),
+        }
+    }
+}
+
+impl From<Error> for io::Error {
+    fn from(data: Error) -> io::Error {
+        io::Error::new(io::ErrorKind::InvalidData, data)
+    }
+}
@@ -10,13 +10,15 @@
 //!
 //! ```
 //! use std::io::prelude::*;
-//! use lzip::read::{LzEncoder, LzDecoder};
+//! use lzip::read::LzDecoder;
+//! use lzip::write::LzEncoder;
 //!
 //! // Round trip some bytes from a byte source, into a compressor, into a
 //! // decompressor, and finally into a vector.
-//! let data = "Hello, World!".as_bytes();
-//! let compressor = LzEncoder::new(data, 9);
-//! let mut decompressor = LzDecoder::new(compressor);
+//! let mut compressor = LzEncoder::new(Vec::new(), 9);
+//! compressor.write_all(b"Hello, World!").unwrap();
+//! let compressed = compressor.finish().unwrap();
+//! let mut decompressor = LzDecoder::new(&compressed[..]);
 //!
 //! let mut contents = String::new();
 //! decompressor.read_to_string(&mut contents).unwrap();
@@ -47,8 +49,32 @@
 #![doc(html_root_url = "https://docs.rs/lzip/")]
 
 extern crate libc;
+extern crate lzip_sys;
+
+pub use carve::carve;
+pub use concat::{concat, ConcatError};
+pub use crc::Crc32;
+pub use dump::{dump, remove, strip, Selection};
+pub use list::{list, Summary};
+pub use mem::{Compress, DecodeError, Decompress, Error, Status, Truncation};
+pub use recover::{merge, repair, Damage, MergeError, Repair, RepairError};
+pub use split::{split, split_to_files};
+pub use verify::{test, Report};
 
 pub mod bufread;
+pub mod format;
+pub mod index;
+pub mod parser;
 pub mod read;
+pub mod write;
 
+mod carve;
+mod concat;
+mod crc;
+mod dump;
+mod list;
 mod mem;
+mod recover;
+mod split;
+mod tester;
+mod verify;
@@ -0,0 +1,79 @@
+//! Summaries of lzip files in the style of `lzip --list`.
+
+use std::io;
+use std::io::prelude::*;
+
+use index::Index;
+
+/// Sizes and properties of an lzip file, as reported by `lzip --list`.
+#[derive(PartialEq, Eq, Copy, Debug, Clone)]
+pub struct Summary {
+    /// Total size of the decompressed data.
+    pub data_size: u64,
+    /// Size of all members, excluding trailing data.
+    pub compressed_size: u64,
+    /// Number of members in the file.
+    pub members: usize,
+    /// Largest dictionary size declared by any member.
+    pub dictionary_size: u32,
+    /// Number of bytes of trailing data after the last member.
+    pub trailing_size: u64,
+}
+
+impl Summary {
+    /// Returns the compression ratio, the decompressed size divided by the
+    /// compressed size.
+    pub fn ratio(&self) -> f64 {
+        if self.compressed_size == 0 {
+            return 0.0;
+        }
+        self.data_size as f64 / self.compressed_size as f64
+    }
+
+    /// Returns the fraction of space saved by compression, as a percentage.
+    ///
+    /// This is negative if the compressed data is larger than the original.
+    pub fn saved(&self) -> f64 {
+        if self.data_size == 0 {
+            return 0.0;
+        }
+        100.0 - 100.0 * self.compressed_size as f64 / self.data_size as f64
+    }
+}
+
+impl<'a> From<&'a Index> for Summary {
+    fn from(index: &'a Index) -> Summary {
+        Summary {
+            data_size: index.data_size(),
+            compressed_size: index.compressed_size(),
+            members: index.members().len(),
+            dictionary_size: index
+                .members()
+                .iter()
+                .map(|m| m.dictionary_size)
+                .max()
+                .unwrap_or(0),
+            trailing_size: index.trailing_size(),
+        }
+    }
+}
+
+/// Summarizes the lzip file read from `r` without decompressing it.
+///
+/// Only the member headers and trailers are read, see `index::Index`.
+///
+/// # Example
+///
+/// ```no_run
+/// use std::fs::File;
+///
+/// let mut file = File::open("archive.lz").unwrap();
+/// let summary = lzip::list(&mut file).unwrap();
+/// println!(
+///     "{} -> {} bytes in {} members ({:.3}:1)",
+///     summary.compressed_size, summary.data_size, summary.members, summary.ratio()
+/// );
+/// ```
+pub fn list<R: Read + Seek>(r: &mut R) -> io::Result<Summary> {
+    Index::new(r).map(|index| Summary::from(&index))
+}
@@ -1,12 +1,24 @@
 //! Raw low-level manipulations of lzip streams.
 
+use std::cmp;
 use std::error;
 use std::fmt;
 use std::marker;
-use std::mem;
 use std::slice;
 
-use libc::{c_int, c_uint};
+use libc::c_int;
+
+use format::{self, Header, Trailer, HEADER_SIZE, TRAILER_SIZE};
+
+/// Representation of an in-memory compression stream.
+///
+/// An instance of `Compress` can be used to compress a stream of bytes into
+/// a single lzip member.
+pub struct Compress {
+    inner: Stream<DirCompress>,
+    total_in: u64,
+    total_out: u64,
+}
 
 /// Representation of an in-memory decompression stream.
 ///
@@ -14,11 +26,25 @@ use libc::{c_int, c_uint};
 /// data.
 pub struct Decompress {
     inner: Stream<DirDecompress>,
+    total_in: u64,
+    total_out: u64,
+    max_dictionary_size: Option<u32>,
+    max_output_size: Option<u64>,
+    max_ratio: Option<u64>,
+    // Compressed bytes handed to lzlib but not yet consumed by it, along with
+    // up to a trailer's worth of bytes before them, starting at stream offset
+    // `lookahead_start`. They let the next member header be read before
+    // lzlib reads it and allocates the dictionary, and the last trailer be
+    // read back when lzlib rejects it.
+    lookahead: Vec<u8>,
+    lookahead_start: u64,
+    header_pending: bool,
+    header: Option<Header>,
 }
 
 struct Stream<D: Direction> {
-    // lzlib requires a stable address for this stream.
-    raw: Box<lzip_sys::LZ_Decoder>,
+    // lzlib allocates the coder itself and requires a stable address for it.
+    raw: *mut D::Raw,
     _marker: marker::PhantomData<D>,
 }
 
@@ -26,9 +52,11 @@ unsafe impl<D: Direction> Send for Stream<D> {}
 unsafe impl<D: Direction> Sync for Stream<D> {}
 
 trait Direction {
-    unsafe fn destroy(stream: *mut lzip_sys::LZ_Decoder) -> c_int;
+    type Raw;
+    unsafe fn destroy(stream: *mut Self::Raw) -> c_int;
 }
 
+enum DirCompress {}
 enum DirDecompress {}
 
 /// Result of compression or decompression
@@ -37,7 +65,15 @@ pub enum Status {
     /// Decompression went fine, nothing much to report.
     Ok,
 
-    /// There was insulzip_syscient memory in the input or output buffer to complete
+    /// The current member has been fully decompressed and its trailer
+    /// verified. Decompression continues with the next member, if any.
+    MemberEnd,
+
+    /// All input has been decompressed and the end of the last member has
+    /// been reached. This follows the `MemberEnd` of the last member.
+    StreamEnd,
+
+    /// There was insufficient memory in the input or output buffer to complete
     /// the request, but otherwise everything went normally.
     MemNeeded,
 }
@@ -52,56 +88,367 @@ pub enum Error {
     /// were invalid. See methods for details.
     Sequence,
 
-    /// The data being decompressed was invalid, or it was not a valid bz2
+    /// The data being decompressed was invalid, or it was not a valid lzip
     /// stream.
     Data,
 
-    /// The magic bz2 header wasn't present when decompressing.
+    /// The magic lzip header wasn't present when decompressing.
     DataMagic,
 
+    /// A member header was found whose magic bytes are partly wrong, in a
+    /// stream with previous members.
+    CorruptHeader,
+
+    /// A member header or trailer is invalid, as described by the inner
+    /// error.
+    Format(format::Error),
+
+    /// The end of the input was reached in the middle of a member.
+    UnexpectedEof {
+        /// The part of the member that was cut short.
+        part: Truncation,
+        /// Number of bytes produced before the end of the input, over all
+        /// members.
+        produced: u64,
+    },
+
     /// The parameters to this function were invalid.
     Param,
+
+    /// Not enough memory could be allocated for the stream.
+    Mem,
+
+    /// A member header declared a dictionary larger than the configured
+    /// maximum, see `Decompress::set_max_dictionary_size`.
+    DictionaryTooLarge {
+        /// Dictionary size declared by the member header.
+        size: u32,
+        /// The configured maximum dictionary size.
+        limit: u32,
+    },
+
+    /// Decompression would produce more output than the configured maximum,
+    /// see `Decompress::set_max_output_size`.
+    OutputTooLarge {
+        /// Number of bytes produced before decompression was stopped.
+        produced: u64,
+        /// The configured maximum output size.
+        limit: u64,
+    },
+
+    /// Decompression would expand the input by more than the configured
+    /// maximum ratio, see `Decompress::set_max_ratio`.
+    RatioTooLarge {
+        /// Number of bytes produced before decompression was stopped.
+        produced: u64,
+        /// Number of input bytes accepted at that point.
+        consumed: u64,
+        /// The configured maximum ratio.
+        limit: u64,
+    },
+
+    /// lzlib reported an internal error.
+    Library,
+}
+
+/// The part of a member in which the input ended, see
+/// `Error::UnexpectedEof`.
+#[derive(PartialEq, Eq, Copy, Debug, Clone)]
+pub enum Truncation {
+    /// The member header is incomplete.
+    Header,
+    /// The compressed data of the member is incomplete.
+    Data,
+    /// The compressed data is complete but the trailer is not, so all the
+    /// data of the member has been produced, though it can't be checked.
+    Trailer,
+}
+
+/// An error found while decoding an lzip stream, along with where it was
+/// found.
+///
+/// Decoders return this inside the `io::Error` for failures in the
+/// compressed data, so that it can be recovered with `io::Error::get_ref`
+/// and `downcast_ref`. `test` reports it in `Report::error`.
+#[derive(PartialEq, Eq, Copy, Debug, Clone)]
+pub struct DecodeError {
+    /// Number of the failing member, counting from zero.
+    pub member: u64,
+    /// Offset in the input of the start of the failing member.
+    pub member_offset: u64,
+    /// Offset in the input where the error was detected.
+    pub pos: u64,
+    /// Number of decompressed bytes produced before the error, over all
+    /// members.
+    pub total_out: u64,
+    /// The error itself.
+    pub error: Error,
+}
+
+/// Dictionary size and match length limit of each compression level, as used
+/// by lzip.
+const LEVELS: [(c_int, c_int); 10] = [
+    (65535, 16),
+    (1 << 20, 5),
+    (3 << 19, 6),
+    (1 << 21, 8),
+    (3 << 20, 12),
+    (1 << 22, 20),
+    (1 << 23, 36),
+    (1 << 24, 68),
+    (3 << 23, 132),
+    (1 << 25, 273),
+];
+
+/// Largest member size lzlib accepts, used to produce a single member.
+const MAX_MEMBER_SIZE: u64 = 0x0008_0000_0000_0000;
+
+impl Compress {
+    /// Creates a new stream prepared for compression at the given level.
+    ///
+    /// Levels range from 0, the fastest, to 9, the best compression, with
+    /// the same meaning as for lzip; higher levels are treated as 9.
+    pub fn new(level: u32) -> Compress {
+        let (dictionary_size, match_len_limit) = LEVELS[cmp::min(level, 9) as usize];
+        unsafe {
+            let raw = lzip_sys::LZ_compress_open(dictionary_size, match_len_limit, MAX_MEMBER_SIZE);
+            assert!(!raw.is_null());
+            assert_eq!(lzip_sys::LZ_compress_errno(raw), lzip_sys::LZ_ERRNO_LZ_OK);
+            Compress {
+                inner: Stream {
+                    raw,
+                    _marker: marker::PhantomData,
+                },
+                total_in: 0,
+                total_out: 0,
+            }
+        }
+    }
+
+    /// Compress a block of input into a block of output.
+    ///
+    /// Not all of `input` may be accepted when the stream's buffers are
+    /// full; `total_in` tells how much was. Once all input has been
+    /// provided, `finish` must be called and this called until it returns
+    /// `Status::StreamEnd` to get the rest of the member.
+    pub fn compress(&mut self, input: &[u8], output: &mut [u8]) -> Result<Status, Error> {
+        let raw = self.inner.raw;
+        unsafe {
+            if !input.is_empty() {
+                let size = cmp::min(input.len(), c_int::MAX as usize) as c_int;
+                let written = lzip_sys::LZ_compress_write(raw, input.as_ptr(), size);
+                if written < 0 {
+                    return Err(self.errno());
+                }
+                self.total_in += written as u64;
+            }
+
+            let size = cmp::min(output.len(), c_int::MAX as usize) as c_int;
+            let read = lzip_sys::LZ_compress_read(raw, output.as_mut_ptr(), size);
+            if read < 0 {
+                return Err(self.errno());
+            }
+            self.total_out += read as u64;
+
+            Ok(if lzip_sys::LZ_compress_finished(raw) == 1 {
+                Status::StreamEnd
+            } else if lzip_sys::LZ_compress_member_finished(raw) == 1 {
+                Status::MemberEnd
+            } else {
+                Status::Ok
+            })
+        }
+    }
+
+    /// Compress a block of input into an output vector.
+    ///
+    /// This function will not grow `output`, but it will fill the space after
+    /// its current length up to its capacity. The length of the vector will be
+    /// adjusted appropriately.
+    pub fn compress_vec(&mut self, input: &[u8], output: &mut Vec<u8>) -> Result<Status, Error> {
+        let cap = output.capacity();
+        let len = output.len();
+
+        unsafe {
+            let before = self.total_out();
+            let ret = {
+                let ptr = output.as_mut_ptr().add(len);
+                let out = slice::from_raw_parts_mut(ptr, cap - len);
+                self.compress(input, out)
+            };
+            output.set_len((self.total_out() - before) as usize + len);
+            ret
+        }
+    }
+
+    /// Tells the stream that all input has been provided, so that the
+    /// member can be completed.
+    pub fn finish(&mut self) -> Result<(), Error> {
+        unsafe {
+            if lzip_sys::LZ_compress_finish(self.inner.raw) < 0 {
+                return Err(self.errno());
+            }
+        }
+        Ok(())
+    }
+
+    /// Asks the stream to make all input provided so far decodable from
+    /// the output, without ending the member.
+    ///
+    /// The flushed data becomes available from subsequent calls to
+    /// `compress`.
+    pub fn sync_flush(&mut self) -> Result<(), Error> {
+        unsafe {
+            if lzip_sys::LZ_compress_sync_flush(self.inner.raw) < 0 {
+                return Err(self.errno());
+            }
+        }
+        Ok(())
+    }
+
+    /// Starts a new member once the current one has been completed, that
+    /// is after `compress` returned `Status::StreamEnd`.
+    pub fn restart_member(&mut self) -> Result<(), Error> {
+        unsafe {
+            if lzip_sys::LZ_compress_restart_member(self.inner.raw, MAX_MEMBER_SIZE) < 0 {
+                return Err(self.errno());
+            }
+        }
+        Ok(())
+    }
+
+    /// Total number of bytes accepted as input by this stream.
+    pub fn total_in(&self) -> u64 {
+        self.total_in
+    }
+
+    /// Total number of bytes produced by this stream.
+    pub fn total_out(&self) -> u64 {
+        self.total_out
+    }
+
+    fn errno(&self) -> Error {
+        unsafe { errno_error(lzip_sys::LZ_compress_errno(self.inner.raw)) }
+    }
 }
 
 impl Decompress {
     /// Creates a new stream prepared for decompression.
     ///
-    /// If `small` is true, then the library will use an alternative
-    /// decompression algorithm which uses less memory but at the cost of
-    /// decompressing more slowly (roughly speaking, half the speed, but the
-    /// maximum memory requirement drops to around 2300k). See
-    pub fn new(small: bool) -> Decompress {
+    /// The stream decodes all members it is given, one after another.
+    pub fn new() -> Decompress {
         unsafe {
-            let mut raw = Box::new(mem::zeroed());
-            //assert_eq!(
-            //lzip_sys::LZ_decompress_read(&mut *raw, , small as c_int),
-            //0
-            //);
+            let raw = lzip_sys::LZ_decompress_open();
+            assert!(!raw.is_null());
+            assert_eq!(lzip_sys::LZ_decompress_errno(raw), lzip_sys::LZ_ERRNO_LZ_OK);
             Decompress {
                 inner: Stream {
-                    raw: raw,
+                    raw,
                     _marker: marker::PhantomData,
                 },
+                total_in: 0,
+                total_out: 0,
+                max_dictionary_size: None,
+                max_output_size: None,
+                max_ratio: None,
+                lookahead: Vec::new(),
+                lookahead_start: 0,
+                header_pending: true,
+                header: None,
             }
         }
     }
 
+    /// Limits the dictionary size a member header may declare.
+    ///
+    /// Every member header is checked before lzlib allocates the dictionary
+    /// for it, and decompression fails with `Error::DictionaryTooLarge` if
+    /// the declared size exceeds `limit`. This bounds the memory used when
+    /// decoding untrusted data.
+    pub fn set_max_dictionary_size(&mut self, limit: Option<u32>) {
+        self.max_dictionary_size = limit;
+    }
+
+    /// Limits the total number of bytes this stream may produce.
+    ///
+    /// Output is capped at `limit` bytes, and decompression fails with
+    /// `Error::OutputTooLarge` as soon as more data would follow.
+    pub fn set_max_output_size(&mut self, limit: Option<u64>) {
+        self.max_output_size = limit;
+    }
+
+    /// Limits how much the output may expand relative to the input.
+    ///
+    /// Output is capped at `limit` times the number of input bytes accepted
+    /// so far. Input keeps being accepted while output is held back, and
+    /// decompression fails with `Error::RatioTooLarge` once no more input
+    /// can be taken and more data would follow.
+    pub fn set_max_ratio(&mut self, limit: Option<u64>) {
+        self.max_ratio = limit;
+    }
+
     /// Decompress a block of input into a block of output.
+    ///
+    /// Once all input has been provided, `finish` must be called so that the
+    /// last member can be completed.
     pub fn decompress(&mut self, input: &[u8], output: &mut [u8]) -> Result<Status, Error> {
-        //self.inner.raw.next_in = input.as_ptr() as *mut _;
-        //self.inner.raw.avail_in = input.len() as c_uint;
-        //self.inner.raw.next_out = output.as_mut_ptr() as *mut _;
-        //self.inner.raw.avail_out = output.len() as c_uint;
+        let raw = self.inner.raw;
         unsafe {
-            match lzip_sys::LZ_decompress_errno(&mut *self.inner.raw) {
-                lzip_sys::LZ_OK => Ok(Status::Ok),
-                lzip_sys::LZ_MEM_ERROR => Ok(Status::MemNeeded),
-                lzip_sys::LZ_BAD_ARGUMENT => Err(Error::Param),
-                lzip_sys::LZ_DATA_ERROR => Err(Error::Data),
-                lzip_sys::LZ_HEADER_ERROR => Err(Error::DataMagic),
-                lzip_sys::LZ_SEQUENCE_ERROR => Err(Error::Sequence),
-                c => panic!("wut: {}", c),
+            let mut written = 0;
+            if !input.is_empty() {
+                let size = cmp::min(input.len(), c_int::MAX as usize) as c_int;
+                written = lzip_sys::LZ_decompress_write(raw, input.as_ptr(), size);
+                if written < 0 {
+                    // After an error lzlib takes no more input, but still
+                    // returns the data decoded before it, and then the
+                    // error, from `LZ_decompress_read`.
+                    written = 0;
+                }
+                self.lookahead.extend_from_slice(&input[..written as usize]);
+                self.total_in += written as u64;
+            }
+            self.trim_lookahead();
+
+            self.read_header()?;
+
+            let mut size = cmp::min(output.len(), c_int::MAX as usize);
+            let read = match self.output_allowance() {
+                Some((0, err)) => {
+                    if written > 0 {
+                        // More input may raise the allowance again.
+                        return Ok(Status::Ok);
+                    }
+                    let mut probe = [0u8; 1];
+                    match lzip_sys::LZ_decompress_read(raw, probe.as_mut_ptr(), 1) {
+                        n if n > 0 => return Err(err),
+                        n if n < 0 => return Err(self.errno()),
+                        _ => 0,
+                    }
+                }
+                Some((allowance, _)) => {
+                    size = cmp::min(size as u64, allowance) as usize;
+                    lzip_sys::LZ_decompress_read(raw, output.as_mut_ptr(), size as c_int)
+                }
+                None => lzip_sys::LZ_decompress_read(raw, output.as_mut_ptr(), size as c_int),
+            };
+            if read < 0 {
+                return Err(self.errno());
+            }
+            self.total_out += read as u64;
+
+            let status = if lzip_sys::LZ_decompress_member_finished(raw) == 1 {
+                Status::MemberEnd
+            } else if lzip_sys::LZ_decompress_finished(raw) == 1 {
+                Status::StreamEnd
+            } else if read == 0 && input.is_empty() {
+                Status::MemNeeded
+            } else {
+                Status::Ok
+            };
+            if status == Status::MemberEnd || status == Status::StreamEnd {
+                self.header_pending = true;
             }
+            Ok(status)
         }
     }
 
@@ -115,17 +462,296 @@ impl Decompress {
         let len = output.len();
 
         unsafe {
-            let before = lzip_sys::LZ_decompress_total_out_size(mem::zeroed());
+            let before = self.total_out();
             let ret = {
-                let ptr = output.as_mut_ptr().offset(len as isize);
+                let ptr = output.as_mut_ptr().add(len);
                 let out = slice::from_raw_parts_mut(ptr, cap - len);
                 self.decompress(input, out)
             };
-            output.set_len(
-                (lzip_sys::LZ_decompress_total_out_size(mem::zeroed()) - before) as usize + len,
-            );
-            return ret;
+            output.set_len((self.total_out() - before) as usize + len);
+            ret
+        }
+    }
+
+    /// Tells the stream that all input has been provided.
+    ///
+    /// After this, `decompress` only drains the data already written to the
+    /// stream and reports `Status::StreamEnd` once the last member is done.
+    pub fn finish(&mut self) -> Result<(), Error> {
+        unsafe {
+            if lzip_sys::LZ_decompress_finish(self.inner.raw) < 0 {
+                return Err(self.errno());
+            }
+        }
+        Ok(())
+    }
+
+    /// Discards the current member and skips input up to the next member
+    /// header, so that decompression can continue after an error.
+    ///
+    /// The search continues through later calls to `decompress` if no
+    /// header is found in the input written so far.
+    pub fn sync_to_member(&mut self) -> Result<(), Error> {
+        unsafe {
+            if lzip_sys::LZ_decompress_sync_to_member(self.inner.raw) < 0 {
+                return Err(self.errno());
+            }
+        }
+        self.header_pending = true;
+        self.header = None;
+        Ok(())
+    }
+
+    /// Total number of bytes accepted as input by this stream.
+    pub fn total_in(&self) -> u64 {
+        self.total_in
+    }
+
+    /// Total number of bytes produced by this stream.
+    pub fn total_out(&self) -> u64 {
+        self.total_out
+    }
+
+    /// Offset in the input of the start of the current member.
+    ///
+    /// After `Status::MemberEnd` this and the other member accessors keep
+    /// describing the member just finished until `decompress` is called
+    /// again.
+    pub fn member_offset(&self) -> u64 {
+        unsafe {
+            lzip_sys::LZ_decompress_total_in_size(self.inner.raw)
+                - lzip_sys::LZ_decompress_member_position(self.inner.raw)
+        }
+    }
+
+    /// Number of input bytes of the current member consumed so far.
+    pub fn member_position(&self) -> u64 {
+        unsafe { lzip_sys::LZ_decompress_member_position(self.inner.raw) }
+    }
+
+    /// Number of bytes of the current member decompressed so far.
+    pub fn data_position(&self) -> u64 {
+        unsafe { lzip_sys::LZ_decompress_data_position(self.inner.raw) }
+    }
+
+    /// CRC32 of the data of the current member decompressed so far.
+    pub fn data_crc(&self) -> u32 {
+        unsafe { lzip_sys::LZ_decompress_data_crc(self.inner.raw) }
+    }
+
+    /// Header of the current member, exactly as it appears in the input.
+    ///
+    /// This is `None` until the header has been written to the stream, and
+    /// for a member found by `sync_to_member`.
+    pub fn header(&self) -> Option<Header> {
+        self.header
+    }
+
+    /// Dictionary size declared by the header of the current member.
+    pub fn dictionary_size(&self) -> u32 {
+        unsafe { lzip_sys::LZ_decompress_dictionary_size(self.inner.raw) as u32 }
+    }
+
+    /// Describes `error`, found in member number `member`, with the position
+    /// this stream reached.
+    pub(crate) fn decode_error(&self, member: u64, error: Error) -> DecodeError {
+        let (member_offset, pos) = match error {
+            // The header is checked before lzlib starts the member, so the
+            // member accessors still describe the previous one.
+            Error::DictionaryTooLarge { .. } => {
+                let offset = unsafe { lzip_sys::LZ_decompress_total_in_size(self.inner.raw) };
+                (offset, offset + HEADER_SIZE as u64 - 1)
+            }
+            _ => (
+                self.member_offset(),
+                self.member_offset() + self.member_position(),
+            ),
+        };
+        DecodeError {
+            member,
+            member_offset,
+            pos,
+            total_out: self.total_out,
+            error,
+        }
+    }
+
+    /// Reads the header of the member about to be started and checks it
+    /// against the dictionary size limit.
+    ///
+    /// Headers that are not valid lzip headers are left for lzlib to report.
+    fn read_header(&mut self) -> Result<(), Error> {
+        let pending = &self.lookahead[self.unconsumed()..];
+        if !self.header_pending || pending.len() < HEADER_SIZE {
+            return Ok(());
         }
+        self.header_pending = false;
+        self.header = Header::parse(pending).ok();
+        match (self.header, self.max_dictionary_size) {
+            (Some(header), Some(limit)) if header.dictionary_size() > limit => {
+                Err(Error::DictionaryTooLarge {
+                    size: header.dictionary_size(),
+                    limit,
+                })
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Drops the bytes lzlib has consumed from the lookahead, except for the
+    /// last `TRAILER_SIZE` of them.
+    fn trim_lookahead(&mut self) {
+        let skip = self.unconsumed().saturating_sub(TRAILER_SIZE);
+        self.lookahead.drain(..skip);
+        self.lookahead_start += skip as u64;
+    }
+
+    /// Returns the index in the lookahead of the first byte not yet consumed
+    /// by lzlib.
+    fn unconsumed(&self) -> usize {
+        let consumed = unsafe { lzip_sys::LZ_decompress_total_in_size(self.inner.raw) };
+        (consumed - self.lookahead_start) as usize
+    }
+
+    /// Returns how many more bytes the configured limits allow this stream
+    /// to produce, along with the error to report once that is exceeded.
+    fn output_allowance(&self) -> Option<(u64, Error)> {
+        let mut allowance = None;
+        if let Some(limit) = self.max_output_size {
+            let err = Error::OutputTooLarge {
+                produced: self.total_out,
+                limit,
+            };
+            allowance = Some((limit.saturating_sub(self.total_out), err));
+        }
+        if let Some(limit) = self.max_ratio {
+            let left = self
+                .total_in
+                .saturating_mul(limit)
+                .saturating_sub(self.total_out);
+            if allowance.is_none_or(|(n, _)| left < n) {
+                let err = Error::RatioTooLarge {
+                    produced: self.total_out,
+                    consumed: self.total_in,
+                    limit,
+                };
+                allowance = Some((left, err));
+            }
+        }
+        allowance
+    }
+
+    /// Returns the error for input ending in the middle of the current
+    /// member.
+    pub(crate) fn unexpected_eof(&self) -> Error {
+        let part = if self.member_position() < HEADER_SIZE as u64 {
+            Truncation::Header
+        } else if self.trailer_started() {
+            Truncation::Trailer
+        } else {
+            Truncation::Data
+        };
+        Error::UnexpectedEof {
+            part,
+            produced: self.total_out,
+        }
+    }
+
+    /// Checks whether the input ends with the start of the trailer of the
+    /// current member, which lzlib doesn't report.
+    ///
+    /// A trailer cut right after the compressed data looks like truncated
+    /// data, as no byte of it is left to compare.
+    fn trailer_started(&self) -> bool {
+        let end = self.member_position();
+        let consumed = unsafe { lzip_sys::LZ_decompress_total_in_size(self.inner.raw) };
+        if consumed != self.total_in {
+            return false;
+        }
+        let tail = &self.lookahead;
+        let max = cmp::min(tail.len() as u64, end - HEADER_SIZE as u64) as usize;
+        (1..cmp::min(max + 1, TRAILER_SIZE)).any(|n| {
+            let trailer = Trailer {
+                data_crc: self.data_crc(),
+                data_size: self.data_position(),
+                member_size: end - n as u64 + TRAILER_SIZE as u64,
+            };
+            tail[tail.len() - n..] == trailer.to_bytes()[..n]
+        })
+    }
+
+    /// Tells apart the data errors lzlib reports, which only differ in
+    /// where they leave the member position.
+    fn data_error(&self) -> Error {
+        let pos = self.member_position();
+        if pos <= HEADER_SIZE as u64 {
+            // lzlib points at the first wrong byte of a bad header.
+            return match pos {
+                4 => {
+                    let version = unsafe { lzip_sys::LZ_decompress_member_version(self.inner.raw) };
+                    Error::Format(format::Error::UnsupportedVersion(version as u8))
+                }
+                5 => Error::Format(format::Error::InvalidDictionarySize(self.dictionary_size())),
+                _ => Error::CorruptHeader,
+            };
+        }
+        match self.trailer_mismatch() {
+            Some(e) => Error::Format(e),
+            None => Error::Data,
+        }
+    }
+
+    /// Returns the mismatch between the trailer just rejected by lzlib and
+    /// the member it ends.
+    ///
+    /// lzlib reports a bad trailer as a data error after reading it, so the
+    /// 20 bytes before the current position are taken as a trailer if at
+    /// least one of its fields is right. Otherwise the error is most likely
+    /// in the compressed data, and a trailer destroyed altogether is
+    /// reported the same way.
+    fn trailer_mismatch(&self) -> Option<format::Error> {
+        let end = self.unconsumed();
+        if end < TRAILER_SIZE {
+            return None;
+        }
+        let trailer = Trailer::parse(&self.lookahead[end - TRAILER_SIZE..end]).ok()?;
+        let (crc, data_size, member_size) = (
+            self.data_crc(),
+            self.data_position(),
+            self.member_position(),
+        );
+        if trailer.data_crc != crc
+            && trailer.data_size != data_size
+            && trailer.member_size != member_size
+        {
+            return None;
+        }
+        trailer.validate(crc, data_size, member_size).err()
+    }
+
+    fn errno(&self) -> Error {
+        match unsafe { lzip_sys::LZ_decompress_errno(self.inner.raw) } {
+            lzip_sys::LZ_ERRNO_LZ_UNEXPECTED_EOF => self.unexpected_eof(),
+            lzip_sys::LZ_ERRNO_LZ_DATA_ERROR => self.data_error(),
+            errno => errno_error(errno),
+        }
+    }
+}
+
+fn errno_error(errno: lzip_sys::LzErrno) -> Error {
+    match errno {
+        lzip_sys::LZ_ERRNO_LZ_BAD_ARGUMENT => Error::Param,
+        lzip_sys::LZ_ERRNO_LZ_MEM_ERROR => Error::Mem,
+        lzip_sys::LZ_ERRNO_LZ_SEQUENCE_ERROR => Error::Sequence,
+        lzip_sys::LZ_ERRNO_LZ_HEADER_ERROR => Error::DataMagic,
+        lzip_sys::LZ_ERRNO_LZ_DATA_ERROR => Error::Data,
+        _ => Error::Library,
+    }
+}
+
+impl Default for Decompress {
+    fn default() -> Decompress {
+        Decompress::new()
     }
 }
 
@@ -136,29 +762,87 @@ impl fmt::Display for Error {
         let description = match self {
             Error::Sequence => "lzip: sequence of operations invalid",
             Error::Data => "lzip: invalid data",
-            Error::DataMagic => "lzip: lz header missing",
+            Error::DataMagic => "lzip: bad magic number (file not in lzip format)",
+            Error::CorruptHeader => "lzip: corrupt header in multimember file",
+            Error::Format(e) => return e.fmt(f),
+            Error::UnexpectedEof { part, .. } => match part {
+                Truncation::Header => "lzip: file ends unexpectedly at member header",
+                Truncation::Data => "lzip: file ends unexpectedly in member data",
+                Truncation::Trailer => "lzip: file ends unexpectedly in member trailer",
+            },
             Error::Param => "lzip: invalid parameters",
+            Error::Mem => "lzip: not enough memory",
+            Error::DictionaryTooLarge { size, limit } => {
+                return write!(
+                    f,
+                    "lzip: dictionary size {} exceeds limit of {}",
+                    size, limit
+                );
+            }
+            Error::OutputTooLarge { produced, limit } => {
+                return write!(
+                    f,
+                    "lzip: output exceeds limit of {} bytes ({} bytes produced)",
+                    limit, produced
+                );
+            }
+            Error::RatioTooLarge {
+                produced,
+                consumed,
+                limit,
+            } => {
+                return write!(
+                    f,
+                    "lzip: expansion ratio exceeds limit of {} ({} bytes produced from {})",
+                    limit, produced, consumed
+                );
+            }
+            Error::Library => "lzip: library error",
         };
         f.write_str(description)
     }
 }
 
+impl error::Error for DecodeError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        Some(&self.error)
+    }
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}: member {} at offset {}, detected at offset {} after {} bytes of output",
+            self.error, self.member, self.member_offset, self.pos, self.total_out
+        )
+    }
+}
+
 impl From<Error> for std::io::Error {
     fn from(data: Error) -> std::io::Error {
-        std::io::Error::new(std::io::ErrorKind::Other, data)
+        std::io::Error::other(data)
+    }
+}
+
+impl Direction for DirCompress {
+    type Raw = lzip_sys::LZ_Encoder;
+    unsafe fn destroy(stream: *mut lzip_sys::LZ_Encoder) -> c_int {
+        lzip_sys::LZ_compress_close(stream)
     }
 }
 
 impl Direction for DirDecompress {
+    type Raw = lzip_sys::LZ_Decoder;
     unsafe fn destroy(stream: *mut lzip_sys::LZ_Decoder) -> c_int {
-        lzip_sys::LZ_decompress_finish(stream)
+        lzip_sys::LZ_decompress_close(stream)
     }
 }
 
 impl<D: Direction> Drop for Stream<D> {
     fn drop(&mut self) {
         unsafe {
-            let _ = D::destroy(&mut *self.raw);
+            let _ = D::destroy(self.raw);
         }
     }
 }
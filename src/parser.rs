@@ -0,0 +1,427 @@
+//! A pull parser reporting the structure of lzip streams as events.
+//!
+//! `Parser` walks a stream member by member and returns an `Event` for every
+//! member boundary and block of decompressed data, so that tools can follow
+//! the layout of a stream without tracking it themselves.
+
+use std::cmp;
+use std::collections::VecDeque;
+use std::io;
+use std::io::prelude::*;
+use std::ops::Range;
+
+use bufread::{decode_error, decompress_step};
+use format::{Header, Trailer, HEADER_SIZE};
+use mem::{Decompress, Error, Status};
+use tester::{Scan, Tester};
+
+/// A single step in the structure of an lzip stream, returned by
+/// `Parser::next_event`.
+#[derive(PartialEq, Eq, Debug)]
+pub enum Event<'a> {
+    /// A new member starts, with the given header.
+    MemberStart(Header),
+
+    /// Decompressed data of the current member.
+    Data(&'a [u8]),
+
+    /// A sync flush marker in the current member. All the data compressed
+    /// before the marker has been returned, and the data after it follows.
+    ///
+    /// This is only reported when enabled with `Parser::sync_points`.
+    SyncPoint,
+
+    /// The current member ends. Its trailer has been checked against the
+    /// decompressed data.
+    MemberEnd(Trailer),
+
+    /// Data that is not an lzip member follows the last member, starting at
+    /// the given offset. No more events follow.
+    TrailingData(u64),
+}
+
+/// A pull parser over the members of an lzip stream.
+///
+/// # Example
+///
+/// ```no_run
+/// use std::fs::File;
+/// use std::io::BufReader;
+/// use lzip::parser::{Event, Parser};
+///
+/// let file = File::open("archive.lz").unwrap();
+/// let mut parser = Parser::new(BufReader::new(file));
+/// while let Some(event) = parser.next_event().unwrap() {
+///     match event {
+///         Event::MemberStart(header) => println!("dictionary {}", header.dictionary_size()),
+///         Event::MemberEnd(trailer) => println!("{} bytes", trailer.data_size),
+///         Event::TrailingData(pos) => println!("trailing data at {}", pos),
+///         _ => {}
+///     }
+/// }
+/// ```
+pub struct Parser<R> {
+    obj: R,
+    data: Decompress,
+    buf: Vec<u8>,
+    eof: bool,
+    members: u64,
+    member_end: u64,
+    state: State,
+    // Data decoded into `buf` and not returned yet.
+    held: Range<usize>,
+    sync: Option<SyncPoints>,
+}
+
+/// The search for sync flush markers, which lzlib doesn't report.
+///
+/// A `Tester` decodes the input accepted by the decompressor once more,
+/// and data is held back until it is known whether a marker comes first.
+struct SyncPoints {
+    // Decoder of the current member, unless the member ended or can't be
+    // followed.
+    tester: Option<Tester<'static>>,
+    // Input accepted by the decompressor, from stream offset `input_start`.
+    input: Vec<u8>,
+    input_start: u64,
+    member_offset: u64,
+    // Data positions in the current member of the markers found and not
+    // yet reported.
+    markers: VecDeque<u64>,
+    // Amount of data of the current member returned so far.
+    data_pos: u64,
+}
+
+/// A reader recording the input consumed from the reader it wraps.
+struct Recorder<'a, R: 'a> {
+    inner: &'a mut R,
+    seen: &'a mut Vec<u8>,
+}
+
+enum State {
+    /// Waiting for the header of the next member.
+    Header,
+    /// Inside the data of a member.
+    Data,
+    /// The member has ended and its trailer is yet to be reported.
+    Trailer(Trailer),
+    /// The stream has ended.
+    Done,
+}
+
+/// An event without the borrowed data, see `Parser::advance`.
+enum Next {
+    MemberStart(Header),
+    Data(Range<usize>),
+    SyncPoint,
+    MemberEnd(Trailer),
+    TrailingData(u64),
+    End,
+}
+
+/// What happened in a single call to the decompressor.
+struct Step {
+    read: usize,
+    status: Status,
+}
+
+impl<R: BufRead> Parser<R> {
+    /// Creates a new parser reading the lzip stream from `r`.
+    pub fn new(r: R) -> Parser<R> {
+        Parser {
+            obj: r,
+            data: Decompress::new(),
+            buf: vec![0; 64 * 1024],
+            eof: false,
+            members: 0,
+            member_end: 0,
+            state: State::Header,
+            held: 0..0,
+            sync: None,
+        }
+    }
+
+    /// Enables `Event::SyncPoint` for the sync flush markers in the stream.
+    ///
+    /// lzlib doesn't report these markers, so finding them means decoding
+    /// the stream a second time, which about doubles the time parsing
+    /// takes. Markers in a damaged member may be missed.
+    pub fn sync_points(mut self, flag: bool) -> Parser<R> {
+        self.sync = if flag {
+            Some(SyncPoints {
+                tester: None,
+                input: Vec::new(),
+                input_start: 0,
+                member_offset: 0,
+                markers: VecDeque::new(),
+                data_pos: 0,
+            })
+        } else {
+            None
+        };
+        self
+    }
+
+    /// Returns the next event, or `None` once the stream has ended.
+    ///
+    /// Errors in the compressed data, including trailers not matching the
+    /// decompressed data and a stream not starting with a member, are
+    /// returned as errors.
+    pub fn next_event(&mut self) -> io::Result<Option<Event<'_>>> {
+        Ok(match self.advance()? {
+            Next::MemberStart(header) => Some(Event::MemberStart(header)),
+            Next::Data(range) => Some(Event::Data(&self.buf[range])),
+            Next::SyncPoint => Some(Event::SyncPoint),
+            Next::MemberEnd(trailer) => Some(Event::MemberEnd(trailer)),
+            Next::TrailingData(pos) => Some(Event::TrailingData(pos)),
+            Next::End => None,
+        })
+    }
+
+    fn advance(&mut self) -> io::Result<Next> {
+        loop {
+            if let Some(next) = self.next_held() {
+                return Ok(next);
+            }
+            match self.state {
+                State::Done => return Ok(Next::End),
+                State::Trailer(trailer) => {
+                    if let Some(ref mut sync) = self.sync {
+                        sync.tester = None;
+                    }
+                    self.state = State::Header;
+                    self.members += 1;
+                    self.member_end = self.data.member_offset() + trailer.member_size;
+                    return Ok(Next::MemberEnd(trailer));
+                }
+                State::Header => {
+                    let step = match self.step(false)? {
+                        Some(step) => step,
+                        None => return Ok(Next::TrailingData(self.member_end)),
+                    };
+                    match step.status {
+                        Status::StreamEnd => {
+                            self.state = State::Done;
+                            return Ok(Next::End);
+                        }
+                        // An empty member, started and finished at once.
+                        Status::MemberEnd => {
+                            self.state = State::Trailer(self.trailer());
+                            return Ok(Next::MemberStart(self.start_member()));
+                        }
+                        _ if self.data.member_position() >= HEADER_SIZE as u64 => {
+                            self.state = State::Data;
+                            return Ok(Next::MemberStart(self.start_member()));
+                        }
+                        Status::MemNeeded if self.eof => {
+                            return Err(self.error(self.data.unexpected_eof()))
+                        }
+                        _ => {}
+                    }
+                }
+                State::Data => {
+                    let step = match self.step(true)? {
+                        Some(step) => step,
+                        None => return Ok(Next::TrailingData(self.member_end)),
+                    };
+                    match step.status {
+                        Status::MemberEnd => self.state = State::Trailer(self.trailer()),
+                        Status::StreamEnd => self.state = State::Done,
+                        Status::MemNeeded if self.eof && step.read == 0 => {
+                            return Err(self.error(self.data.unexpected_eof()))
+                        }
+                        _ => {}
+                    }
+                    self.held = 0..step.read;
+                }
+            }
+        }
+    }
+
+    /// Runs the decompressor once, decoding into the internal buffer if
+    /// `output` is set. Returns `None` if trailing data was found.
+    fn step(&mut self, output: bool) -> io::Result<Option<Step>> {
+        let out = if output {
+            &mut self.buf[..]
+        } else {
+            &mut [][..]
+        };
+        let (read, ret) = match self.sync {
+            Some(ref mut sync) => {
+                // A new member can't start before the input lzlib has
+                // consumed, so only the current member needs what's before.
+                if sync.tester.is_none() {
+                    sync.trim(self.data.member_offset() + self.data.member_position());
+                }
+                let mut r = Recorder {
+                    inner: &mut self.obj,
+                    seen: &mut sync.input,
+                };
+                let step = decompress_step(&mut r, &mut self.data, &mut self.eof, out)?;
+                sync.scan();
+                step
+            }
+            None => decompress_step(&mut self.obj, &mut self.data, &mut self.eof, out)?,
+        };
+        let status = match ret {
+            Ok(status) => status,
+            Err(Error::DataMagic) if self.members > 0 => {
+                self.state = State::Done;
+                return Ok(None);
+            }
+            Err(e) => return Err(decode_error(&self.data, self.members, e)),
+        };
+        Ok(Some(Step { read, status }))
+    }
+
+    fn error(&self, e: Error) -> io::Error {
+        decode_error(&self.data, self.members, e)
+    }
+
+    /// Returns the header of the member just started, starting the search
+    /// for markers in it.
+    fn start_member(&mut self) -> Header {
+        let header = self.data.header().expect("header of a started member");
+        if let Some(ref mut sync) = self.sync {
+            let offset = self.data.member_offset();
+            sync.trim(offset);
+            if sync.input_start == offset {
+                sync.tester = Some(Tester::new(header.dictionary_size()));
+            }
+            sync.member_offset = offset;
+            sync.markers.clear();
+            sync.data_pos = 0;
+            sync.scan();
+        }
+        header
+    }
+
+    /// Returns the next part of the held data, or a sync point at the
+    /// current position.
+    fn next_held(&mut self) -> Option<Next> {
+        let sync = match self.sync {
+            Some(ref mut sync) => sync,
+            None if self.held.is_empty() => return None,
+            None => {
+                let range = self.held.start..self.held.end;
+                self.held.start = self.held.end;
+                return Some(Next::Data(range));
+            }
+        };
+        if sync.markers.front() == Some(&sync.data_pos) {
+            sync.markers.pop_front();
+            return Some(Next::SyncPoint);
+        }
+        if self.held.is_empty() {
+            return None;
+        }
+        let mut len = self.held.len() as u64;
+        if let Some(&marker) = sync.markers.front() {
+            len = cmp::min(len, marker - sync.data_pos);
+        }
+        // The tester decodes whatever lzlib decoded from the same input,
+        // unless the input is cut short or damaged, in which case lzlib
+        // reports an error once its data has been returned.
+        let lagging = sync
+            .tester
+            .as_ref()
+            .is_some_and(|t| t.data_position() < sync.data_pos + len);
+        if lagging {
+            sync.tester = None;
+        }
+        sync.data_pos += len;
+        let start = self.held.start;
+        self.held.start += len as usize;
+        Some(Next::Data(start..self.held.start))
+    }
+
+    fn trailer(&self) -> Trailer {
+        Trailer {
+            data_crc: self.data.data_crc(),
+            data_size: self.data.data_position(),
+            member_size: self.data.member_position(),
+        }
+    }
+}
+
+impl<R> Parser<R> {
+    /// Acquires a reference to the underlying reader.
+    pub fn get_ref(&self) -> &R {
+        &self.obj
+    }
+
+    /// Acquires a mutable reference to the underlying stream.
+    ///
+    /// Note that mutation of the stream may result in surprising results if
+    /// this parser is continued to be used.
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.obj
+    }
+
+    /// Consumes this parser, returning the underlying reader.
+    pub fn into_inner(self) -> R {
+        self.obj
+    }
+
+    /// Returns the number of compressed bytes consumed so far.
+    pub fn total_in(&self) -> u64 {
+        self.data.total_in()
+    }
+
+    /// Returns the number of decompressed bytes returned so far.
+    pub fn total_out(&self) -> u64 {
+        self.data.total_out() - self.held.len() as u64
+    }
+}
+
+impl SyncPoints {
+    /// Decodes the input recorded so far, noting the markers found.
+    fn scan(&mut self) {
+        while let Some(ref mut tester) = self.tester {
+            let start = (self.input_start - self.member_offset) as usize;
+            match tester.scan(&self.input, start) {
+                Scan::SyncFlush => self.markers.push_back(tester.data_position()),
+                Scan::NeedInput => {
+                    let pos = self.member_offset + tester.member_position() as u64;
+                    self.trim(pos);
+                    return;
+                }
+                Scan::End | Scan::Invalid => self.tester = None,
+            }
+        }
+    }
+
+    /// Drops the input before stream offset `pos`.
+    fn trim(&mut self, pos: u64) {
+        let n = cmp::min(pos.saturating_sub(self.input_start), self.input.len() as u64);
+        self.input.drain(..n as usize);
+        self.input_start += n;
+    }
+}
+
+impl<'a, R: BufRead> Read for Recorder<'a, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = {
+            let input = self.fill_buf()?;
+            let n = cmp::min(input.len(), buf.len());
+            buf[..n].copy_from_slice(&input[..n]);
+            n
+        };
+        self.consume(n);
+        Ok(n)
+    }
+}
+
+impl<'a, R: BufRead> BufRead for Recorder<'a, R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        self.inner.fill_buf()
+    }
+
+    fn consume(&mut self, amt: usize) {
+        // The buffer is already filled, as `consume` follows `fill_buf`.
+        if let Ok(input) = self.inner.fill_buf() {
+            self.seen.extend_from_slice(&input[..amt]);
+        }
+        self.inner.consume(amt);
+    }
+}
@@ -1,7 +1,9 @@
 //! Reader-based compression/decompression streams
 
+use std::cmp;
 use std::io::prelude::*;
-use std::io::{self, BufReader};
+use std::io::{self, BufReader, SeekFrom, Take};
+use std::ops::Range;
 
 #[cfg(feature = "tokio")]
 use futures::Poll;
@@ -9,6 +11,8 @@ use futures::Poll;
 use tokio_io::{AsyncRead, AsyncWrite};
 
 use bufread;
+use index::Index;
+use recover::Damage;
 
 /// A decompression stream which wraps a compressed stream of data. Decompressed
 /// data will be read from the stream.
@@ -25,6 +29,33 @@ impl<R: Read> LzDecoder<R> {
         }
     }
 
+    /// Limits the dictionary size that a member header may declare.
+    ///
+    /// See `bufread::LzDecoder::max_dictionary_size`.
+    pub fn max_dictionary_size(self, limit: u32) -> LzDecoder<R> {
+        LzDecoder {
+            inner: self.inner.max_dictionary_size(limit),
+        }
+    }
+
+    /// Limits the total number of decompressed bytes.
+    ///
+    /// See `bufread::LzDecoder::max_output_size`.
+    pub fn max_output_size(self, limit: u64) -> LzDecoder<R> {
+        LzDecoder {
+            inner: self.inner.max_output_size(limit),
+        }
+    }
+
+    /// Limits the expansion ratio of decompressed to compressed bytes.
+    ///
+    /// See `bufread::LzDecoder::max_ratio`.
+    pub fn max_ratio(self, limit: u64) -> LzDecoder<R> {
+        LzDecoder {
+            inner: self.inner.max_ratio(limit),
+        }
+    }
+
     /// Acquires a reference to the underlying stream
     pub fn get_ref(&self) -> &R {
         self.inner.get_ref().get_ref()
@@ -103,6 +134,33 @@ impl<R: Read> MultiLzDecoder<R> {
             inner: bufread::MultiLzDecoder::new(BufReader::new(r)),
         }
     }
+
+    /// Limits the dictionary size that a member header may declare.
+    ///
+    /// See `bufread::LzDecoder::max_dictionary_size`.
+    pub fn max_dictionary_size(self, limit: u32) -> MultiLzDecoder<R> {
+        MultiLzDecoder {
+            inner: self.inner.max_dictionary_size(limit),
+        }
+    }
+
+    /// Limits the total number of decompressed bytes.
+    ///
+    /// See `bufread::LzDecoder::max_output_size`.
+    pub fn max_output_size(self, limit: u64) -> MultiLzDecoder<R> {
+        MultiLzDecoder {
+            inner: self.inner.max_output_size(limit),
+        }
+    }
+
+    /// Limits the expansion ratio of decompressed to compressed bytes.
+    ///
+    /// See `bufread::LzDecoder::max_ratio`.
+    pub fn max_ratio(self, limit: u64) -> MultiLzDecoder<R> {
+        MultiLzDecoder {
+            inner: self.inner.max_ratio(limit),
+        }
+    }
 }
 
 impl<R> MultiLzDecoder<R> {
@@ -150,3 +208,384 @@ impl<R: AsyncWrite + AsyncRead> AsyncWrite for MultiLzDecoder<R> {
         self.get_mut().shutdown()
     }
 }
+
+/// An lzip decoder that skips damaged members instead of failing.
+///
+/// See `bufread::RecoveringLzDecoder`.
+///
+/// # Example
+///
+/// ```no_run
+/// use std::fs::File;
+/// use std::io;
+/// use lzip::read::RecoveringLzDecoder;
+///
+/// let mut decoder = RecoveringLzDecoder::new(File::open("damaged.lz").unwrap());
+/// io::copy(&mut decoder, &mut File::create("recovered").unwrap()).unwrap();
+/// for damage in decoder.damage() {
+///     println!(
+///         "lost input {:?}, suspect output {:?}: {}",
+///         damage.compressed_range(),
+///         damage.data_range(),
+///         damage.error
+///     );
+/// }
+/// ```
+pub struct RecoveringLzDecoder<R> {
+    inner: bufread::RecoveringLzDecoder<BufReader<R>>,
+}
+
+impl<R: Read> RecoveringLzDecoder<R> {
+    /// Creates a new recovering decoder reading from the given reader.
+    pub fn new(r: R) -> RecoveringLzDecoder<R> {
+        RecoveringLzDecoder {
+            inner: bufread::RecoveringLzDecoder::new(BufReader::new(r)),
+        }
+    }
+
+    /// Limits the dictionary size that a member header may declare.
+    ///
+    /// See `bufread::LzDecoder::max_dictionary_size`.
+    pub fn max_dictionary_size(self, limit: u32) -> RecoveringLzDecoder<R> {
+        RecoveringLzDecoder {
+            inner: self.inner.max_dictionary_size(limit),
+        }
+    }
+
+    /// Limits the total number of decompressed bytes.
+    ///
+    /// See `bufread::LzDecoder::max_output_size`.
+    pub fn max_output_size(self, limit: u64) -> RecoveringLzDecoder<R> {
+        RecoveringLzDecoder {
+            inner: self.inner.max_output_size(limit),
+        }
+    }
+
+    /// Limits the expansion ratio of decompressed to compressed bytes.
+    ///
+    /// See `bufread::LzDecoder::max_ratio`.
+    pub fn max_ratio(self, limit: u64) -> RecoveringLzDecoder<R> {
+        RecoveringLzDecoder {
+            inner: self.inner.max_ratio(limit),
+        }
+    }
+}
+
+impl<R> RecoveringLzDecoder<R> {
+    /// Returns the damaged regions found so far, in the order they appear.
+    ///
+    /// See `bufread::RecoveringLzDecoder::damage`.
+    pub fn damage(&self) -> &[Damage] {
+        self.inner.damage()
+    }
+
+    /// Acquires a reference to the underlying reader.
+    pub fn get_ref(&self) -> &R {
+        self.inner.get_ref().get_ref()
+    }
+
+    /// Acquires a mutable reference to the underlying stream.
+    ///
+    /// Note that mutation of the stream may result in surprising results if
+    /// this decoder is continued to be used.
+    pub fn get_mut(&mut self) -> &mut R {
+        self.inner.get_mut().get_mut()
+    }
+
+    /// Consumes this decoder, returning the underlying reader.
+    pub fn into_inner(self) -> R {
+        self.inner.into_inner().into_inner()
+    }
+
+    /// Returns the number of bytes produced by the decompressor.
+    pub fn total_out(&self) -> u64 {
+        self.inner.total_out()
+    }
+
+    /// Returns the number of bytes consumed by the decompressor.
+    pub fn total_in(&self) -> u64 {
+        self.inner.total_in()
+    }
+}
+
+impl<R: Read> Read for RecoveringLzDecoder<R> {
+    fn read(&mut self, into: &mut [u8]) -> io::Result<usize> {
+        self.inner.read(into)
+    }
+}
+
+/// A reader over the members of a multi-member lzip stream, one at a time.
+///
+/// See `bufread::Members`.
+pub struct Members<R> {
+    inner: bufread::Members<BufReader<R>>,
+}
+
+impl<R: Read> Members<R> {
+    /// Creates a new reader over the members of the stream read from `r`.
+    pub fn new(r: R) -> Members<R> {
+        Members {
+            inner: bufread::Members::new(BufReader::new(r)),
+        }
+    }
+
+    /// Advances to the next member, returning `None` at the end of the
+    /// stream.
+    ///
+    /// See `bufread::Members::next_member`.
+    pub fn next_member(&mut self) -> io::Result<Option<bufread::MemberReader<'_, BufReader<R>>>> {
+        self.inner.next_member()
+    }
+}
+
+impl<R> Members<R> {
+    /// Acquires a reference to the underlying reader.
+    pub fn get_ref(&self) -> &R {
+        self.inner.get_ref().get_ref()
+    }
+
+    /// Acquires a mutable reference to the underlying stream.
+    ///
+    /// Note that mutation of the stream may result in surprising results if
+    /// this reader is continued to be used.
+    pub fn get_mut(&mut self) -> &mut R {
+        self.inner.get_mut().get_mut()
+    }
+
+    /// Consumes this reader, returning the underlying reader.
+    pub fn into_inner(self) -> R {
+        self.inner.into_inner().into_inner()
+    }
+}
+
+/// A decoder providing random access to the decompressed data of a seekable
+/// lzip file.
+///
+/// The members of the file are located with an `Index` when the decoder is
+/// created. Seeking then jumps to the member containing the target offset,
+/// and only the part of that member before the target is decoded again.
+/// A member read from is decoded through its trailer, and checked against
+/// it, before its last byte is returned or another member is read.
+///
+/// # Example
+///
+/// ```no_run
+/// use std::fs::File;
+/// use std::io::prelude::*;
+/// use std::io::SeekFrom;
+/// use lzip::read::SeekableLzDecoder;
+///
+/// let file = File::open("logs.lz").unwrap();
+/// let mut decoder = SeekableLzDecoder::new(file).unwrap();
+/// decoder.seek(SeekFrom::Start(1 << 30)).unwrap();
+/// let mut line = [0; 80];
+/// decoder.read_exact(&mut line).unwrap();
+/// ```
+pub struct SeekableLzDecoder<R> {
+    index: Index,
+    // Exactly one of `obj` and `decoder` is set; the decoder owns the
+    // underlying reader while a member is being decoded.
+    obj: Option<R>,
+    decoder: Option<bufread::LzDecoder<BufReader<Take<R>>>>,
+    member: usize,
+    decoded: u64,
+    pos: u64,
+}
+
+impl<R: Read + Seek> SeekableLzDecoder<R> {
+    /// Creates a new decoder over the lzip file read from `r`, building its
+    /// member index first.
+    pub fn new(mut r: R) -> io::Result<SeekableLzDecoder<R>> {
+        let index = Index::new(&mut r)?;
+        Ok(SeekableLzDecoder::with_index(r, index))
+    }
+
+    /// Creates a new decoder over the lzip file read from `r`, using an
+    /// already built index of it.
+    pub fn with_index(r: R, index: Index) -> SeekableLzDecoder<R> {
+        SeekableLzDecoder {
+            index,
+            obj: Some(r),
+            decoder: None,
+            member: 0,
+            decoded: 0,
+            pos: 0,
+        }
+    }
+
+    /// Starts decoding member `i` from its beginning.
+    fn start(&mut self, i: usize) -> io::Result<()> {
+        let member = self.index.members()[i];
+        let mut obj = self.take_obj();
+        if let Err(e) = obj.seek(SeekFrom::Start(member.member_offset)) {
+            self.obj = Some(obj);
+            return Err(e);
+        }
+        let r = BufReader::new(obj.take(member.member_size));
+        self.decoder = Some(bufread::LzDecoder::new(r));
+        self.member = i;
+        self.decoded = member.data_offset;
+        Ok(())
+    }
+
+    /// Decodes the rest of the current member through its trailer, so that
+    /// none of its data is handed out without being checked.
+    fn finish(&mut self) -> io::Result<()> {
+        let mut scratch = [0; 8192];
+        loop {
+            match self.decoder.as_mut().unwrap().read(&mut scratch)? {
+                0 => break,
+                n => self.decoded += n as u64,
+            }
+        }
+        let member = self.index.members()[self.member];
+        if self.decoded != member.data_offset + member.data_size {
+            return Err(size_mismatch());
+        }
+        let obj = self.take_obj();
+        self.obj = Some(obj);
+        Ok(())
+    }
+
+    fn take_obj(&mut self) -> R {
+        match self.decoder.take() {
+            Some(decoder) => decoder.into_inner().into_inner().into_inner(),
+            None => self.obj.take().unwrap(),
+        }
+    }
+}
+
+impl<R> SeekableLzDecoder<R> {
+    /// Returns the index of the members of the underlying file.
+    pub fn index(&self) -> &Index {
+        &self.index
+    }
+
+    /// Acquires a reference to the underlying stream
+    pub fn get_ref(&self) -> &R {
+        match self.decoder {
+            Some(ref decoder) => decoder.get_ref().get_ref().get_ref(),
+            None => self.obj.as_ref().unwrap(),
+        }
+    }
+
+    /// Acquires a mutable reference to the underlying stream
+    ///
+    /// Note that mutation of the stream may result in surprising results if
+    /// this decoder is continued to be used.
+    pub fn get_mut(&mut self) -> &mut R {
+        match self.decoder {
+            Some(ref mut decoder) => decoder.get_mut().get_mut().get_mut(),
+            None => self.obj.as_mut().unwrap(),
+        }
+    }
+
+    /// Consumes this decoder, returning the underlying reader.
+    pub fn into_inner(self) -> R {
+        match self.decoder {
+            Some(decoder) => decoder.into_inner().into_inner().into_inner(),
+            None => self.obj.unwrap(),
+        }
+    }
+}
+
+impl<R: Read + Seek> Read for SeekableLzDecoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() || self.pos >= self.index.data_size() {
+            return Ok(0);
+        }
+        let i = self.index.find(self.pos).unwrap();
+        if self.decoder.is_some() && self.member != i {
+            self.finish()?;
+        }
+        if self.decoder.is_none() || self.member != i || self.decoded > self.pos {
+            self.start(i)?;
+        }
+        let decoder = self.decoder.as_mut().unwrap();
+
+        let mut scratch = [0; 8192];
+        while self.decoded < self.pos {
+            let skip = cmp::min(self.pos - self.decoded, scratch.len() as u64) as usize;
+            match decoder.read(&mut scratch[..skip])? {
+                0 => return Err(size_mismatch()),
+                n => self.decoded += n as u64,
+            }
+        }
+        let n = decoder.read(buf)?;
+        if n == 0 {
+            return Err(size_mismatch());
+        }
+        self.decoded += n as u64;
+        self.pos += n as u64;
+        let member = self.index.members()[i];
+        if self.decoded == member.data_offset + member.data_size {
+            self.finish()?;
+        }
+        Ok(n)
+    }
+}
+
+fn size_mismatch() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        "member data doesn't match the size stored in its trailer",
+    )
+}
+
+impl<R: Read + Seek> Seek for SeekableLzDecoder<R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let (base, offset) = match pos {
+            SeekFrom::Start(n) => {
+                self.pos = n;
+                return Ok(n);
+            }
+            SeekFrom::End(n) => (self.index.data_size(), n),
+            SeekFrom::Current(n) => (self.pos, n),
+        };
+        match base.checked_add_signed(offset) {
+            Some(n) => {
+                self.pos = n;
+                Ok(n)
+            }
+            None => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "invalid seek to a negative or overflowing position",
+            )),
+        }
+    }
+}
+
+/// Decompresses the bytes of `range` of the decompressed data of the lzip
+/// file read from `r`, writing them to `w`.
+///
+/// Only the members overlapping `range` are decoded, each of them through
+/// its trailer, so an error is returned if any of them is damaged. The range
+/// is clamped to the size of the decompressed data, and the number of bytes
+/// written is returned.
+///
+/// # Example
+///
+/// ```no_run
+/// use std::fs::File;
+///
+/// let file = File::open("object.lz").unwrap();
+/// let mut body = Vec::new();
+/// lzip::read::decompress_range(file, 4096..8192, &mut body).unwrap();
+/// ```
+pub fn decompress_range<R, W>(r: R, range: Range<u64>, w: &mut W) -> io::Result<u64>
+where
+    R: Read + Seek,
+    W: Write + ?Sized,
+{
+    let mut decoder = SeekableLzDecoder::new(r)?;
+    if range.start >= range.end || range.start >= decoder.index().data_size() {
+        return Ok(0);
+    }
+    decoder.seek(SeekFrom::Start(range.start))?;
+    let n = io::copy(&mut (&mut decoder).take(range.end - range.start), w)?;
+    // The range may end inside a member, which has to be checked still.
+    if decoder.decoder.is_some() {
+        decoder.finish()?;
+    }
+    Ok(n)
+}
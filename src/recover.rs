@@ -0,0 +1,521 @@
+//! Recovery of damaged lzip files in the style of `lziprecover`.
+
+use std::cmp;
+use std::error;
+use std::fmt;
+use std::io;
+use std::io::prelude::*;
+use std::io::SeekFrom;
+use std::ops::Range;
+
+use bufread::decompress_step;
+use format::{Trailer, HEADER_SIZE, TRAILER_SIZE};
+use index::{Index, Member};
+use mem::{Decompress, Error, Status};
+use tester::{Outcome, Tester};
+use verify;
+
+/// Most combinations of differing regions `merge` tries for one member.
+const MAX_COMBINATIONS: u64 = 1 << 12;
+
+/// Number of bytes before the point where decoding failed that `repair`
+/// tries changing, as in `lziprecover`.
+const REPAIR_WINDOW: usize = 50_000;
+
+/// Number of bytes `repair` tries after decoding the member up to them once.
+const REPAIR_BLOCK: usize = 100;
+
+/// A damaged region skipped by a recovering decoder, see
+/// `read::RecoveringLzDecoder`.
+#[derive(PartialEq, Eq, Copy, Debug, Clone)]
+pub struct Damage {
+    /// Offset in the input of the start of the damaged member.
+    pub member_offset: u64,
+    /// Offset in the input where decoding resumed, at the next member header
+    /// found or at the end of the input.
+    pub resync_offset: u64,
+    /// Offset in the decompressed output where the data of the damaged
+    /// member starts.
+    pub data_offset: u64,
+    /// Number of bytes of the damaged member that were output before the
+    /// error was found. They could not be verified and may be corrupt; the
+    /// rest of the member's data is lost.
+    pub data_size: u64,
+    /// The error that was found.
+    pub error: Error,
+}
+
+impl Damage {
+    /// Returns the range of compressed input that was skipped.
+    pub fn compressed_range(&self) -> Range<u64> {
+        self.member_offset..self.resync_offset
+    }
+
+    /// Returns the range of decompressed output that came from the damaged
+    /// member.
+    pub fn data_range(&self) -> Range<u64> {
+        self.data_offset..self.data_offset + self.data_size
+    }
+}
+
+/// A byte changed by `repair`.
+#[derive(PartialEq, Eq, Copy, Debug, Clone)]
+pub struct Repair {
+    /// Number of the repaired member, counting from zero.
+    pub member: usize,
+    /// Offset of the repaired byte in the file.
+    pub pos: u64,
+    /// Value of the byte in the damaged file.
+    pub old: u8,
+    /// Value of the byte in the repaired file.
+    pub new: u8,
+}
+
+/// Reasons `repair` can fail to repair a file.
+#[derive(PartialEq, Eq, Copy, Debug, Clone)]
+pub enum RepairError {
+    /// The member can't be repaired by changing a single byte.
+    Unrepairable {
+        /// Number of the member, counting from zero.
+        member: usize,
+        /// Offset of the member in the file.
+        member_offset: u64,
+    },
+}
+
+/// Reasons `merge` can fail to rebuild a file.
+#[derive(PartialEq, Eq, Copy, Debug, Clone)]
+pub enum MergeError {
+    /// Fewer than two copies were given.
+    TooFewCopies,
+
+    /// The copies don't all have the same size.
+    SizeMismatch,
+
+    /// No combination of the copies gives a valid member.
+    Unrecoverable {
+        /// Number of the member, counting from zero.
+        member: usize,
+        /// Offset of the member in the file.
+        member_offset: u64,
+    },
+
+    /// The copies of a member differ in too many places to try every
+    /// combination.
+    TooManyDifferences {
+        /// Number of the member, counting from zero.
+        member: usize,
+        /// Offset of the member in the file.
+        member_offset: u64,
+        /// Number of separate regions where the copies differ.
+        regions: usize,
+    },
+}
+
+/// Rebuilds a correct lzip file from several damaged copies of it, like
+/// `lziprecover -m`, writing it to `output`.
+///
+/// The copies must have the same size, and at least one of them must have
+/// intact member headers and trailers so that the file can be indexed. Each
+/// member is taken from the first copy in which it is valid. If it is
+/// damaged in every copy, the regions where the copies differ are located
+/// and combinations of them taken from different copies are tried until one
+/// decompresses to data matching the member's trailer. Trailing data is
+/// copied from the first copy.
+///
+/// The copies are read again for every combination tried rather than held
+/// in memory. Problems that prevent the rebuild are reported with a
+/// `MergeError` wrapped in the `io::Error`.
+///
+/// # Example
+///
+/// ```no_run
+/// use std::fs::File;
+///
+/// let mut copies = vec![File::open("a/backup.lz").unwrap(), File::open("b/backup.lz").unwrap()];
+/// let mut output = File::create("backup.lz").unwrap();
+/// lzip::merge(&mut copies, &mut output).unwrap();
+/// ```
+pub fn merge<R, W>(copies: &mut [R], output: &mut W) -> io::Result<u64>
+where
+    R: Read + Seek,
+    W: Write + ?Sized,
+{
+    if copies.len() < 2 {
+        return Err(MergeError::TooFewCopies.into());
+    }
+    let file_size = copies[0].seek(SeekFrom::End(0))?;
+    for r in copies.iter_mut() {
+        if r.seek(SeekFrom::End(0))? != file_size {
+            return Err(MergeError::SizeMismatch.into());
+        }
+    }
+    let index = first_index(copies)?;
+
+    for (i, member) in index.members().iter().enumerate() {
+        let choice = merge_member(copies, i, member)?;
+        io::copy(&mut Candidate::new(copies, member, &choice), output)?;
+    }
+
+    let end = index.compressed_size();
+    copies[0].seek(SeekFrom::Start(end))?;
+    io::copy(&mut (&mut copies[0]).take(file_size - end), output)?;
+    Ok(file_size)
+}
+
+/// Returns the index of the first copy that can be indexed, or the error
+/// indexing the first copy if none can.
+fn first_index<R: Read + Seek>(copies: &mut [R]) -> io::Result<Index> {
+    let mut first_error = None;
+    for r in copies.iter_mut() {
+        match Index::new(r) {
+            Ok(index) => return Ok(index),
+            Err(e) => {
+                first_error.get_or_insert(e);
+            }
+        }
+    }
+    Err(first_error.unwrap())
+}
+
+/// Finds a valid version of member number `i`, returning the copy to take
+/// each part of it from.
+fn merge_member<R: Read + Seek>(
+    copies: &mut [R],
+    i: usize,
+    member: &Member,
+) -> io::Result<Vec<(Range<u64>, usize)>> {
+    for c in 0..copies.len() {
+        let whole = vec![(0..member.member_size, c)];
+        if is_valid_candidate(copies, member, &whole)? {
+            return Ok(whole);
+        }
+    }
+
+    let regions = differing_regions(copies, member)?;
+    let combinations = (copies.len() as u64)
+        .checked_pow(regions.len() as u32)
+        .unwrap_or(u64::MAX);
+    if combinations > MAX_COMBINATIONS {
+        return Err(MergeError::TooManyDifferences {
+            member: i,
+            member_offset: member.member_offset,
+            regions: regions.len(),
+        }
+        .into());
+    }
+
+    // Count through every choice of copy for each region, with the choices
+    // as the digits of a number in base `copies.len()`.
+    let mut choice: Vec<_> = regions.into_iter().map(|region| (region, 0)).collect();
+    loop {
+        let mut k = 0;
+        while k < choice.len() && choice[k].1 == copies.len() - 1 {
+            choice[k].1 = 0;
+            k += 1;
+        }
+        if k == choice.len() {
+            break;
+        }
+        choice[k].1 += 1;
+
+        if is_valid_candidate(copies, member, &choice)? {
+            return Ok(choice);
+        }
+    }
+    Err(MergeError::Unrecoverable {
+        member: i,
+        member_offset: member.member_offset,
+    }
+    .into())
+}
+
+/// Returns the ranges of bytes of `member` in which not all of `copies`
+/// agree.
+fn differing_regions<R: Read + Seek>(
+    copies: &mut [R],
+    member: &Member,
+) -> io::Result<Vec<Range<u64>>> {
+    let mut buffers = vec![vec![0; 64 * 1024]; copies.len()];
+    for r in copies.iter_mut() {
+        r.seek(SeekFrom::Start(member.member_offset))?;
+    }
+    let mut regions: Vec<Range<u64>> = Vec::new();
+    let mut start = 0;
+    while start < member.member_size {
+        let n = cmp::min(buffers[0].len() as u64, member.member_size - start) as usize;
+        for (r, buf) in copies.iter_mut().zip(&mut buffers) {
+            r.read_exact(&mut buf[..n])?;
+        }
+        for k in 0..n {
+            let b = buffers[0][k];
+            if buffers[1..].iter().all(|buf| buf[k] == b) {
+                continue;
+            }
+            let pos = start + k as u64;
+            match regions.last_mut() {
+                Some(region) if region.end == pos => region.end += 1,
+                _ => regions.push(pos..pos + 1),
+            }
+        }
+        start += n as u64;
+    }
+    Ok(regions)
+}
+
+/// Returns true if the version of `member` put together from `copies` as
+/// described by `choice` is a valid member.
+fn is_valid_candidate<R: Read + Seek>(
+    copies: &mut [R],
+    member: &Member,
+    choice: &[(Range<u64>, usize)],
+) -> io::Result<bool> {
+    let report = verify::test(Candidate::new(copies, member, choice))?;
+    Ok(report.is_ok()
+        && report.trailing_size == 0
+        && report.members.len() == 1
+        && report.members[0].member_size == member.member_size)
+}
+
+/// A version of a member read from several copies of it, taking each range
+/// listed with a copy from that copy and the rest from the first copy.
+struct Candidate<'a, R: 'a> {
+    copies: &'a mut [R],
+    member: &'a Member,
+    choice: &'a [(Range<u64>, usize)],
+    pos: u64,
+}
+
+impl<'a, R: Read + Seek> Candidate<'a, R> {
+    fn new(
+        copies: &'a mut [R],
+        member: &'a Member,
+        choice: &'a [(Range<u64>, usize)],
+    ) -> Candidate<'a, R> {
+        Candidate {
+            copies,
+            member,
+            choice,
+            pos: 0,
+        }
+    }
+}
+
+impl<'a, R: Read + Seek> Read for Candidate<'a, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.pos >= self.member.member_size {
+            return Ok(0);
+        }
+        let pos = self.pos;
+        let (copy, end) = match self.choice.iter().find(|(range, _)| range.end > pos) {
+            Some((range, c)) if range.start <= pos => (*c, range.end),
+            Some((range, _)) => (0, range.start),
+            None => (0, self.member.member_size),
+        };
+        let n = cmp::min(buf.len() as u64, end - pos) as usize;
+        let r = &mut self.copies[copy];
+        r.seek(SeekFrom::Start(self.member.member_offset + pos))?;
+        let n = r.read(&mut buf[..n])?;
+        self.pos += n as u64;
+        Ok(n)
+    }
+}
+
+/// Repairs a lzip file damaged by a single corrupt byte per member, like
+/// `lziprecover -R`, writing the repaired file to `output`.
+///
+/// Every member that fails to decompress is repaired by trying each other
+/// value of each of the 50000 bytes before the point where decompression
+/// failed, until the member decompresses to the data described by its
+/// trailer. A single corrupt byte in a trailer is corrected directly. The
+/// member headers and trailers must allow the file to be indexed.
+///
+/// For every value tried, the rest of the member from that byte on is
+/// decompressed again, so repairing large members can take a long time. The
+/// changes made are returned; a member that can't be repaired makes this
+/// fail with a `RepairError` wrapped in the `io::Error`, and `output` may
+/// then hold part of the file.
+///
+/// # Example
+///
+/// ```no_run
+/// use std::fs::File;
+///
+/// let mut input = File::open("backup.lz").unwrap();
+/// let mut output = File::create("backup_fixed.lz").unwrap();
+/// for repair in lzip::repair(&mut input, &mut output).unwrap() {
+///     println!("byte at pos {} changed from {:#04x} to {:#04x}", repair.pos, repair.old, repair.new);
+/// }
+/// ```
+pub fn repair<R, W>(r: &mut R, output: &mut W) -> io::Result<Vec<Repair>>
+where
+    R: Read + Seek,
+    W: Write + ?Sized,
+{
+    let index = Index::new(r)?;
+    let mut repairs = Vec::new();
+    let mut buf = Vec::new();
+    for (i, member) in index.members().iter().enumerate() {
+        buf.resize(member.member_size as usize, 0);
+        r.seek(SeekFrom::Start(member.member_offset))?;
+        r.read_exact(&mut buf)?;
+        if let Some(failure) = check_member(&buf) {
+            let (pos, old) = match repair_member(&mut buf, member.dictionary_size, failure) {
+                Some(repair) => repair,
+                None => {
+                    return Err(RepairError::Unrepairable {
+                        member: i,
+                        member_offset: member.member_offset,
+                    }
+                    .into())
+                }
+            };
+            repairs.push(Repair {
+                member: i,
+                pos: member.member_offset + pos as u64,
+                old,
+                new: buf[pos],
+            });
+        }
+        output.write_all(&buf)?;
+    }
+
+    let end = index.compressed_size();
+    r.seek(SeekFrom::Start(end))?;
+    io::copy(&mut r.take(index.file_size() - end), output)?;
+    Ok(repairs)
+}
+
+/// Changes a single byte of the damaged member in `data` so that it
+/// becomes valid, returning its position and old value.
+fn repair_member(data: &mut [u8], dictionary_size: u32, failure: Failure) -> Option<(usize, u8)> {
+    let trailer_pos = data.len() - TRAILER_SIZE;
+    if failure.pos == data.len() as u64 {
+        // All the data decoded, so the trailer itself may be damaged.
+        let computed = failure.computed.to_bytes();
+        let mut diffs = (0..TRAILER_SIZE).filter(|&k| data[trailer_pos + k] != computed[k]);
+        if let (Some(k), None) = (diffs.next(), diffs.next()) {
+            let old = data[trailer_pos + k];
+            data[trailer_pos + k] = computed[k];
+            return Some((trailer_pos + k, old));
+        }
+    }
+
+    // The corrupt byte precedes the point where decoding failed, and is
+    // most likely close to it, so blocks of bytes are tried going back from
+    // there. The member is decoded once up to each byte tried, and only the
+    // rest of it is decoded again for each value.
+    let end = cmp::min(failure.pos as usize, trailer_pos);
+    let start = cmp::max(HEADER_SIZE, end.saturating_sub(REPAIR_WINDOW));
+    let mut block_end = end;
+    while block_end > start {
+        let block_start = cmp::max(start, block_end.saturating_sub(REPAIR_BLOCK));
+        let mut master = Tester::new(dictionary_size);
+        for pos in block_start..block_end {
+            if master.decode(data, Some(pos)) != Outcome::Reached {
+                break;
+            }
+            let old = data[pos];
+            for value in (0..=255).filter(|&v| v != old) {
+                data[pos] = value;
+                if master.fork().decode(data, None) == Outcome::Valid {
+                    return Some((pos, old));
+                }
+            }
+            data[pos] = old;
+        }
+        block_end = block_start;
+    }
+    None
+}
+
+/// Where and how decoding a damaged member failed.
+struct Failure {
+    /// Position in the member where the failure was detected.
+    pos: u64,
+    /// Trailer describing the data decoded up to that point.
+    computed: Trailer,
+}
+
+/// Decodes the member in `data`, returning how it failed unless it is a
+/// single member that decompresses to the data described by its trailer.
+fn check_member(data: &[u8]) -> Option<Failure> {
+    let mut stream = Decompress::new();
+    let mut out = vec![0; 16 * 1024];
+    let mut input = data;
+    let mut eof = false;
+    // Reading from a slice can't fail.
+    while let Ok((_, ret)) = decompress_step(&mut input, &mut stream, &mut eof, &mut out) {
+        match ret {
+            Ok(Status::MemberEnd) if stream.member_position() == data.len() as u64 => return None,
+            Ok(Status::Ok) => {}
+            Ok(Status::MemNeeded) if !eof => {}
+            _ => break,
+        }
+    }
+    Some(Failure {
+        pos: stream.member_position(),
+        computed: Trailer {
+            data_crc: stream.data_crc(),
+            data_size: stream.data_position(),
+            member_size: stream.member_position(),
+        },
+    })
+}
+
+impl error::Error for RepairError {}
+
+impl fmt::Display for RepairError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RepairError::Unrepairable {
+                member,
+                member_offset,
+            } => write!(
+                f,
+                "lzip: can't repair member {} at pos {}; error is not a single byte",
+                member, member_offset
+            ),
+        }
+    }
+}
+
+impl From<RepairError> for io::Error {
+    fn from(data: RepairError) -> io::Error {
+        io::Error::new(io::ErrorKind::InvalidData, data)
+    }
+}
+
+impl error::Error for MergeError {}
+
+impl fmt::Display for MergeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MergeError::TooFewCopies => f.write_str("lzip: at least two copies are needed"),
+            MergeError::SizeMismatch => f.write_str("lzip: sizes of input files are different"),
+            MergeError::Unrecoverable {
+                member,
+                member_offset,
+            } => write!(
+                f,
+                "lzip: can't recover member {} at pos {}",
+                member, member_offset
+            ),
+            MergeError::TooManyDifferences {
+                member,
+                member_offset,
+                regions,
+            } => write!(
+                f,
+                "lzip: too many error areas ({}) in member {} at pos {}",
+                regions, member, member_offset
+            ),
+        }
+    }
+}
+
+impl From<MergeError> for io::Error {
+    fn from(data: MergeError) -> io::Error {
+        io::Error::new(io::ErrorKind::InvalidData, data)
+    }
+}
@@ -0,0 +1,88 @@
+//! Splitting of multi-member lzip files in the style of `lziprecover -s`.
+
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::io::prelude::*;
+use std::io::SeekFrom;
+use std::path::Path;
+
+use index::{Index, Member};
+
+/// Copies every member of the lzip file read from `r` to its own writer,
+/// obtained from `factory`, returning the index of the file.
+///
+/// `factory` is called with the number of each member, counting from zero,
+/// and its location. Members are copied byte for byte without being
+/// decompressed, so each writer receives a complete lzip file. Trailing data
+/// after the last member is not copied.
+///
+/// # Example
+///
+/// ```no_run
+/// use std::fs::File;
+///
+/// let mut file = File::open("archive.lz").unwrap();
+/// lzip::split(&mut file, |i, _| File::create(format!("shard{}.lz", i))).unwrap();
+/// ```
+pub fn split<R, F, W>(r: &mut R, mut factory: F) -> io::Result<Index>
+where
+    R: Read + Seek,
+    F: FnMut(usize, &Member) -> io::Result<W>,
+    W: Write,
+{
+    let index = Index::new(r)?;
+    copy_members(r, &index, &mut factory)?;
+    Ok(index)
+}
+
+fn copy_members<R, F, W>(r: &mut R, index: &Index, factory: &mut F) -> io::Result<()>
+where
+    R: Read + Seek,
+    F: FnMut(usize, &Member) -> io::Result<W>,
+    W: Write,
+{
+    for (i, member) in index.members().iter().enumerate() {
+        let mut w = factory(i, member)?;
+        r.seek(SeekFrom::Start(member.member_offset))?;
+        let copied = io::copy(&mut r.take(member.member_size), &mut w)?;
+        if copied != member.member_size {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "input file shrank while being split",
+            ));
+        }
+        w.flush()?;
+    }
+    Ok(())
+}
+
+/// Splits the lzip file at `path` into one file per member, like
+/// `lziprecover -s`.
+///
+/// The files are created next to `path` and named `rec01file.lz`,
+/// `rec02file.lz` and so on for a file named `file.lz`, using as many digits
+/// as needed for the number of members, but at least two, so that they sort
+/// in order. Existing files are not overwritten; finding one is an error.
+pub fn split_to_files<P: AsRef<Path>>(path: P) -> io::Result<Index> {
+    let path = path.as_ref();
+    let name = match path.file_name() {
+        Some(name) => name.to_string_lossy().into_owned(),
+        None => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "lzip: input path has no file name",
+            ))
+        }
+    };
+    let mut file = File::open(path)?;
+    let index = Index::new(&mut file)?;
+    let width = index.members().len().to_string().len().max(2);
+    copy_members(&mut file, &index, &mut |i, _| {
+        let rec = format!("rec{:0width$}{}", i + 1, name, width = width);
+        OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(path.with_file_name(rec))
+    })?;
+    Ok(index)
+}
@@ -0,0 +1,648 @@
+//! A member decoder whose state can be copied, in the style of the member
+//! tester of `lziprecover`.
+//!
+//! lzlib decodes members behind an opaque handle, so trying a change to a
+//! damaged member with it means decoding the member again from the start.
+//! `Tester` instead decodes the undamaged start of the member once and is
+//! then forked for each change tried, so that only the rest of the member
+//! is decoded again.
+//!
+//! lzlib doesn't report sync flush markers either, so `Parser` runs a
+//! `Tester` alongside it to find them.
+
+use std::cmp;
+
+use crc::Crc32;
+use format::{Trailer, HEADER_SIZE, TRAILER_SIZE};
+
+const STATES: usize = 12;
+const POS_STATES: usize = 4;
+const LITERAL_CONTEXT_BITS: u32 = 3;
+const LEN_STATES: usize = 4;
+const START_DIS_MODEL: u32 = 4;
+const END_DIS_MODEL: u32 = 14;
+const MODELED_DISTANCES: usize = 1 << (END_DIS_MODEL / 2);
+const DIS_ALIGN_BITS: u32 = 4;
+const LEN_LOW_SYMBOLS: usize = 8;
+const LEN_MID_SYMBOLS: usize = 8;
+const LEN_HIGH_SYMBOLS: usize = 256;
+const MIN_MATCH_LEN: u32 = 2;
+
+const BIT_MODEL_MOVE_BITS: u32 = 5;
+const BIT_MODEL_TOTAL_BITS: u32 = 11;
+const BIT_MODEL_TOTAL: u16 = 1 << BIT_MODEL_TOTAL_BITS;
+const HALF: u16 = BIT_MODEL_TOTAL / 2;
+
+/// Most bytes of input the range decoder reads for a single LZMA packet.
+const MAX_PACKET_SIZE: usize = 10;
+
+/// How a call to `Tester::decode` ended.
+#[derive(PartialEq, Eq, Copy, Debug, Clone)]
+pub(crate) enum Outcome {
+    /// Decoding stopped before reading the requested position.
+    Reached,
+    /// The member decoded to the end and matches its trailer.
+    Valid,
+    /// The member is damaged.
+    Invalid,
+}
+
+/// Where `Tester::scan` stopped.
+#[derive(PartialEq, Eq, Copy, Debug, Clone)]
+pub(crate) enum Scan {
+    /// The next packet needs bytes past the end of the input.
+    NeedInput,
+    /// A sync flush marker was decoded.
+    SyncFlush,
+    /// The end of member marker was decoded.
+    End,
+    /// The member is damaged.
+    Invalid,
+}
+
+enum Packet {
+    Data,
+    Error,
+    SyncFlush,
+    End,
+}
+
+/// Decoder for a single member, held in memory for `decode` or passed piece
+/// by piece to `scan`.
+///
+/// The member is passed to every call rather than kept, so that it can be
+/// changed between calls. Changing bytes the decoder has already read makes
+/// the results meaningless.
+pub(crate) struct Tester<'a> {
+    rd: RangeDecoder,
+    models: Box<Models>,
+    window: Window<'a>,
+    state: usize,
+    reps: [u32; 4],
+}
+
+#[derive(Clone)]
+struct RangeDecoder {
+    // Position in the member of the next byte to read.
+    pos: usize,
+    // Position in the member of the first byte of the input being decoded.
+    start: usize,
+    code: u32,
+    range: u32,
+    reload_pending: bool,
+}
+
+/// The data decoded so far, as far back as matches can reach.
+struct Window<'a> {
+    dictionary_size: u32,
+    // Data decoded before the tester was forked, shared with the tester it
+    // was forked from, followed by the data decoded since.
+    base: &'a [u8],
+    buf: Vec<u8>,
+    // CRC of the data decoded before `buf[hashed..]`.
+    crc: Crc32,
+    hashed: usize,
+}
+
+#[derive(Clone)]
+struct LenModel {
+    choice1: u16,
+    choice2: u16,
+    low: [[u16; LEN_LOW_SYMBOLS]; POS_STATES],
+    mid: [[u16; LEN_MID_SYMBOLS]; POS_STATES],
+    high: [u16; LEN_HIGH_SYMBOLS],
+}
+
+#[derive(Clone)]
+struct Models {
+    literal: [[u16; 0x300]; 1 << LITERAL_CONTEXT_BITS],
+    is_match: [[u16; POS_STATES]; STATES],
+    rep: [u16; STATES],
+    rep0: [u16; STATES],
+    rep1: [u16; STATES],
+    rep2: [u16; STATES],
+    len: [[u16; POS_STATES]; STATES],
+    dis_slot: [[u16; 64]; LEN_STATES],
+    dis: [u16; MODELED_DISTANCES - END_DIS_MODEL as usize + 1],
+    align: [u16; 1 << DIS_ALIGN_BITS],
+    match_len: LenModel,
+    rep_len: LenModel,
+}
+
+impl Tester<'static> {
+    /// Creates a tester for a member whose header declares
+    /// `dictionary_size`.
+    pub(crate) fn new(dictionary_size: u32) -> Tester<'static> {
+        let len_model = LenModel {
+            choice1: HALF,
+            choice2: HALF,
+            low: [[HALF; LEN_LOW_SYMBOLS]; POS_STATES],
+            mid: [[HALF; LEN_MID_SYMBOLS]; POS_STATES],
+            high: [HALF; LEN_HIGH_SYMBOLS],
+        };
+        Tester {
+            rd: RangeDecoder {
+                pos: HEADER_SIZE,
+                start: 0,
+                code: 0,
+                range: 0xFFFF_FFFF,
+                reload_pending: true,
+            },
+            models: Box::new(Models {
+                literal: [[HALF; 0x300]; 1 << LITERAL_CONTEXT_BITS],
+                is_match: [[HALF; POS_STATES]; STATES],
+                rep: [HALF; STATES],
+                rep0: [HALF; STATES],
+                rep1: [HALF; STATES],
+                rep2: [HALF; STATES],
+                len: [[HALF; POS_STATES]; STATES],
+                dis_slot: [[HALF; 64]; LEN_STATES],
+                dis: [HALF; MODELED_DISTANCES - END_DIS_MODEL as usize + 1],
+                align: [HALF; 1 << DIS_ALIGN_BITS],
+                match_len: len_model.clone(),
+                rep_len: len_model,
+            }),
+            window: Window {
+                dictionary_size,
+                base: &[],
+                buf: Vec::new(),
+                crc: Crc32::new(),
+                hashed: 0,
+            },
+            state: 0,
+            reps: [0; 4],
+        }
+    }
+
+    /// Returns a copy of this tester that continues decoding on its own.
+    ///
+    /// The data decoded so far is shared rather than copied, so forking is
+    /// cheap even with a large dictionary.
+    pub(crate) fn fork(&self) -> Tester<'_> {
+        let mut crc = self.window.crc;
+        crc.update(&self.window.buf[self.window.hashed..]);
+        Tester {
+            rd: self.rd.clone(),
+            models: self.models.clone(),
+            window: Window {
+                dictionary_size: self.window.dictionary_size,
+                base: &self.window.buf,
+                buf: Vec::new(),
+                crc,
+                hashed: 0,
+            },
+            state: self.state,
+            reps: self.reps,
+        }
+    }
+}
+
+impl<'a> Tester<'a> {
+    /// Decodes `member` up to its end, or until the next packet would read
+    /// the byte at `stop`.
+    pub(crate) fn decode(&mut self, member: &[u8], stop: Option<usize>) -> Outcome {
+        if member.len() < HEADER_SIZE + TRAILER_SIZE {
+            return Outcome::Invalid;
+        }
+        let end = member.len() - TRAILER_SIZE;
+        let (member, trailer) = member.split_at(end);
+        self.rd.start = 0;
+        loop {
+            match self.run(member, end, stop) {
+                Scan::NeedInput => return Outcome::Reached,
+                Scan::SyncFlush => {}
+                Scan::End => break,
+                Scan::Invalid => return Outcome::Invalid,
+            }
+        }
+
+        if self.rd.pos != end {
+            return Outcome::Invalid;
+        }
+        let crc = self.window.crc();
+        let valid = Trailer::parse(trailer)
+            .and_then(|t| t.validate(crc.sum(), crc.amount(), (end + TRAILER_SIZE) as u64))
+            .is_ok();
+        if valid {
+            Outcome::Valid
+        } else {
+            Outcome::Invalid
+        }
+    }
+
+    /// Decodes the part of a member in `input`, which starts at offset
+    /// `start` in the member, until the next packet would read past the end
+    /// of `input` or a marker is found.
+    ///
+    /// Unlike `decode`, this doesn't need the whole member at once, nor
+    /// check its trailer. `input` must hold the member from
+    /// `member_position` on.
+    pub(crate) fn scan(&mut self, input: &[u8], start: usize) -> Scan {
+        self.rd.start = start;
+        self.run(input, usize::MAX, Some(start + input.len()))
+    }
+
+    /// Position in the member of the next byte the decoder will read.
+    pub(crate) fn member_position(&self) -> usize {
+        self.rd.pos
+    }
+
+    /// Number of bytes decoded so far.
+    pub(crate) fn data_position(&self) -> u64 {
+        self.window.position()
+    }
+
+    /// Decodes packets until a marker, a packet past `end` or a packet
+    /// reading the byte at `stop`, which is undone.
+    fn run(&mut self, member: &[u8], end: usize, stop: Option<usize>) -> Scan {
+        loop {
+            if self.rd.pos > end {
+                return Scan::Invalid;
+            }
+            if self.rd.reload_pending {
+                if stop.is_some_and(|stop| self.rd.pos + 5 > stop) {
+                    return Scan::NeedInput;
+                }
+                self.rd.reload(member);
+                continue;
+            }
+            let packet = match stop {
+                Some(stop) if self.rd.pos + MAX_PACKET_SIZE > stop => {
+                    // The packet may read the byte at `stop`, in which case
+                    // it is undone.
+                    let rd = self.rd.clone();
+                    let models = self.models.clone();
+                    let (state, reps) = (self.state, self.reps);
+                    let len = self.window.buf.len();
+                    let packet = self.decode_packet(member);
+                    if self.rd.pos > stop {
+                        self.rd = rd;
+                        self.models = models;
+                        self.state = state;
+                        self.reps = reps;
+                        self.window.buf.truncate(len);
+                        return Scan::NeedInput;
+                    }
+                    packet
+                }
+                _ => self.decode_packet(member),
+            };
+            self.window.trim();
+            match packet {
+                Packet::Data => {}
+                Packet::Error => return Scan::Invalid,
+                Packet::SyncFlush => {
+                    self.rd.reload_pending = true;
+                    return Scan::SyncFlush;
+                }
+                Packet::End => return Scan::End,
+            }
+        }
+    }
+
+    fn decode_packet(&mut self, member: &[u8]) -> Packet {
+        let rd = &mut self.rd;
+        let m = &mut *self.models;
+        let w = &mut self.window;
+        let state = self.state;
+        let pos_state = (w.position() % POS_STATES as u64) as usize;
+
+        if rd.bit(member, &mut m.is_match[state][pos_state]) == 0 {
+            let prev = w.peek(0);
+            let bm = &mut m.literal[(prev >> (8 - LITERAL_CONTEXT_BITS)) as usize];
+            self.state = [0, 0, 0, 0, 1, 2, 3, 4, 5, 6, 4, 5][state];
+            let byte = if state < 7 {
+                rd.tree(member, bm, 8)
+            } else {
+                rd.matched(member, bm, w.peek(self.reps[0]))
+            };
+            w.put(byte as u8);
+            return Packet::Data;
+        }
+
+        let len;
+        if rd.bit(member, &mut m.rep[state]) != 0 {
+            if rd.bit(member, &mut m.rep0[state]) == 0 {
+                if rd.bit(member, &mut m.len[state][pos_state]) == 0 {
+                    self.state = if state < 7 { 9 } else { 11 };
+                    let byte = w.peek(self.reps[0]);
+                    w.put(byte);
+                    return Packet::Data;
+                }
+            } else {
+                let distance;
+                if rd.bit(member, &mut m.rep1[state]) == 0 {
+                    distance = self.reps[1];
+                } else {
+                    if rd.bit(member, &mut m.rep2[state]) == 0 {
+                        distance = self.reps[2];
+                    } else {
+                        distance = self.reps[3];
+                        self.reps[3] = self.reps[2];
+                    }
+                    self.reps[2] = self.reps[1];
+                }
+                self.reps[1] = self.reps[0];
+                self.reps[0] = distance;
+            }
+            self.state = if state < 7 { 8 } else { 11 };
+            len = rd.len(member, &mut m.rep_len, pos_state);
+        } else {
+            len = rd.len(member, &mut m.match_len, pos_state);
+            let len_state = cmp::min(len - MIN_MATCH_LEN, LEN_STATES as u32 - 1) as usize;
+            let mut distance = rd.tree(member, &mut m.dis_slot[len_state], 6);
+            if distance >= START_DIS_MODEL {
+                let dis_slot = distance;
+                let direct_bits = (dis_slot >> 1) - 1;
+                distance = (2 | (dis_slot & 1)) << direct_bits;
+                if dis_slot < END_DIS_MODEL {
+                    let start = (distance - dis_slot) as usize;
+                    distance += rd.reversed(member, &mut m.dis[start..], direct_bits);
+                } else {
+                    distance += rd.direct(member, direct_bits - DIS_ALIGN_BITS) << DIS_ALIGN_BITS;
+                    distance += rd.reversed(member, &mut m.align, DIS_ALIGN_BITS);
+                    if distance == 0xFFFF_FFFF {
+                        rd.normalize(member);
+                        return match len {
+                            2 => Packet::End,
+                            3 => Packet::SyncFlush,
+                            _ => Packet::Error,
+                        };
+                    }
+                }
+            }
+            self.reps = [distance, self.reps[0], self.reps[1], self.reps[2]];
+            self.state = if state < 7 { 7 } else { 10 };
+            if distance >= w.dictionary_size || u64::from(distance) >= w.position() {
+                return Packet::Error;
+            }
+        }
+        w.copy(self.reps[0], len as usize);
+        Packet::Data
+    }
+}
+
+impl<'a> Window<'a> {
+    /// Number of bytes decoded so far.
+    fn position(&self) -> u64 {
+        self.crc.amount() + (self.buf.len() - self.hashed) as u64
+    }
+
+    /// Returns the CRC of the data decoded so far.
+    fn crc(&mut self) -> Crc32 {
+        self.crc.update(&self.buf[self.hashed..]);
+        self.hashed = self.buf.len();
+        self.crc
+    }
+
+    /// Returns the byte decoded `distance + 1` bytes ago, or 0 before the
+    /// start of the data like lzlib.
+    fn peek(&self, distance: u32) -> u8 {
+        let d = distance as usize + 1;
+        if d <= self.buf.len() {
+            return self.buf[self.buf.len() - d];
+        }
+        let d = d - self.buf.len();
+        if d <= self.base.len() {
+            self.base[self.base.len() - d]
+        } else {
+            0
+        }
+    }
+
+    fn put(&mut self, byte: u8) {
+        self.buf.push(byte);
+    }
+
+    /// Appends `len` bytes copied from `distance + 1` bytes back.
+    fn copy(&mut self, distance: u32, len: usize) {
+        let d = distance as usize + 1;
+        self.buf.reserve(len);
+        if d <= self.buf.len() {
+            for _ in 0..len {
+                let byte = self.buf[self.buf.len() - d];
+                self.buf.push(byte);
+            }
+        } else {
+            for _ in 0..len {
+                let byte = self.peek(distance);
+                self.buf.push(byte);
+            }
+        }
+    }
+
+    /// Drops the data matches can no longer reach. This is only done
+    /// between packets, so that a packet can be undone by truncating `buf`.
+    fn trim(&mut self) {
+        // Matches reach back at most the dictionary size, so once `buf`
+        // holds twice that the start of it and `base` can be dropped.
+        let keep = cmp::max(self.dictionary_size as usize, 1 << 16);
+        if self.buf.len() >= 2 * keep {
+            let cut = self.buf.len() - keep;
+            self.crc.update(&self.buf[self.hashed..cut]);
+            self.buf.drain(..cut);
+            self.hashed = 0;
+            self.base = &[];
+        }
+    }
+}
+
+impl RangeDecoder {
+    fn byte(&mut self, member: &[u8]) -> u8 {
+        // Like lzlib, read 0xFF past the end of the compressed data.
+        let byte = member.get(self.pos - self.start).copied().unwrap_or(0xFF);
+        self.pos += 1;
+        byte
+    }
+
+    fn reload(&mut self, member: &[u8]) {
+        self.reload_pending = false;
+        self.code = 0;
+        for _ in 0..5 {
+            self.code = (self.code << 8) | u32::from(self.byte(member));
+        }
+        self.range = 0xFFFF_FFFF;
+    }
+
+    fn normalize(&mut self, member: &[u8]) {
+        if self.range <= 0x00FF_FFFF {
+            self.range <<= 8;
+            self.code = (self.code << 8) | u32::from(self.byte(member));
+        }
+    }
+
+    fn bit(&mut self, member: &[u8], probability: &mut u16) -> u32 {
+        self.normalize(member);
+        let bound = (self.range >> BIT_MODEL_TOTAL_BITS) * u32::from(*probability);
+        if self.code < bound {
+            self.range = bound;
+            *probability += (BIT_MODEL_TOTAL - *probability) >> BIT_MODEL_MOVE_BITS;
+            0
+        } else {
+            self.code -= bound;
+            self.range -= bound;
+            *probability -= *probability >> BIT_MODEL_MOVE_BITS;
+            1
+        }
+    }
+
+    fn direct(&mut self, member: &[u8], num_bits: u32) -> u32 {
+        let mut symbol = 0;
+        for _ in 0..num_bits {
+            self.normalize(member);
+            self.range >>= 1;
+            let bit = (self.code >= self.range) as u32;
+            symbol = (symbol << 1) + bit;
+            self.code -= self.range & 0u32.wrapping_sub(bit);
+        }
+        symbol
+    }
+
+    fn tree(&mut self, member: &[u8], bm: &mut [u16], num_bits: u32) -> u32 {
+        let mut symbol = 1;
+        for _ in 0..num_bits {
+            symbol = (symbol << 1) | self.bit(member, &mut bm[symbol as usize]);
+        }
+        symbol - (1 << num_bits)
+    }
+
+    fn reversed(&mut self, member: &[u8], bm: &mut [u16], num_bits: u32) -> u32 {
+        let mut model = 1;
+        let mut symbol = 0;
+        for i in 0..num_bits {
+            let bit = self.bit(member, &mut bm[model as usize]);
+            model = (model << 1) | bit;
+            symbol |= bit << i;
+        }
+        symbol
+    }
+
+    fn matched(&mut self, member: &[u8], bm: &mut [u16], match_byte: u8) -> u32 {
+        let mut match_byte = u32::from(match_byte);
+        let mut symbol = 1;
+        let mut mask = 0x100;
+        loop {
+            match_byte <<= 1;
+            let match_bit = match_byte & mask;
+            let bit = self.bit(member, &mut bm[(symbol + match_bit + mask) as usize]);
+            symbol = (symbol << 1) + bit;
+            if symbol > 0xFF {
+                return symbol & 0xFF;
+            }
+            mask &= !(match_bit ^ (bit << 8));
+        }
+    }
+
+    fn len(&mut self, member: &[u8], lm: &mut LenModel, pos_state: usize) -> u32 {
+        if self.bit(member, &mut lm.choice1) == 0 {
+            return MIN_MATCH_LEN + self.tree(member, &mut lm.low[pos_state], 3);
+        }
+        if self.bit(member, &mut lm.choice2) == 0 {
+            let low = LEN_LOW_SYMBOLS as u32;
+            return MIN_MATCH_LEN + low + self.tree(member, &mut lm.mid[pos_state], 3);
+        }
+        let low_mid = (LEN_LOW_SYMBOLS + LEN_MID_SYMBOLS) as u32;
+        MIN_MATCH_LEN + low_mid + self.tree(member, &mut lm.high, 8)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use format::Header;
+    use mem::Error;
+    use verify;
+
+    fn testsuite(name: &str) -> Vec<u8> {
+        let dir = concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/lzip-sys/lzlib-1.13/testsuite/"
+        );
+        fs::read(format!("{}{}", dir, name)).unwrap()
+    }
+
+    fn tester(member: &[u8]) -> Tester<'static> {
+        Tester::new(Header::parse(member).unwrap().dictionary_size())
+    }
+
+    /// Decodes `member` one more byte at a time, returning the first stop
+    /// not reached and the outcome there.
+    fn first_stop(member: &[u8]) -> (usize, Outcome) {
+        let mut t = tester(member);
+        for stop in HEADER_SIZE..member.len() {
+            match t.decode(member, Some(stop)) {
+                Outcome::Reached => {}
+                outcome => return (stop, outcome),
+            }
+        }
+        panic!("decoding never ends");
+    }
+
+    /// Checks the tester against lzlib on every member of `name`.
+    fn check(name: &str) {
+        let file = testsuite(name);
+        let report = verify::test(&file[..]).unwrap();
+        for m in &report.members {
+            let start = m.member_offset as usize;
+            let member = &file[start..start + m.member_size as usize];
+            let end = member.len() - TRAILER_SIZE;
+            assert_eq!(first_stop(member), (end, Outcome::Valid), "{}", name);
+            assert_eq!(tester(member).decode(member, None), Outcome::Valid);
+        }
+
+        let e = match report.error {
+            Some(e) => e,
+            None => return,
+        };
+        let member = &file[e.member_offset as usize..];
+        let expected = match e.error {
+            // The trailer doesn't match the data, which decodes fine.
+            Error::Format(_) => (member.len() - TRAILER_SIZE, Outcome::Invalid),
+            Error::Data => ((e.pos - e.member_offset) as usize, Outcome::Invalid),
+            other => panic!("{}: unexpected error {:?}", name, other),
+        };
+        assert_eq!(first_stop(member), expected, "{}", name);
+        assert_eq!(tester(member).decode(member, None), Outcome::Invalid);
+    }
+
+    #[test]
+    fn valid_members() {
+        check("fox.lz");
+        check("test.txt.lz");
+        // Sync flush markers and empty members.
+        check("test_sync.lz");
+        check("test_em.txt.lz");
+    }
+
+    #[test]
+    fn damaged_members() {
+        for name in &[
+            "fox_bcrc.lz",
+            "fox_crc0.lz",
+            "fox_das46.lz",
+            "fox_mes81.lz",
+            "fox_de20.lz",
+        ] {
+            check(name);
+        }
+    }
+
+    #[test]
+    fn forks_decode_on_their_own() {
+        for name in &["test.txt.lz", "fox_de20.lz"] {
+            let member = testsuite(name);
+            let outcome = tester(&member).decode(&member, None);
+            for &stop in &[HEADER_SIZE + 1, 20, 32, 1000, 5000] {
+                let mut t = tester(&member);
+                if t.decode(&member, Some(stop)) != Outcome::Reached {
+                    continue;
+                }
+                assert_eq!(t.fork().decode(&member, None), outcome, "{}", name);
+                // The fork changed nothing in the original.
+                assert_eq!(t.decode(&member, None), outcome, "{}", name);
+            }
+        }
+    }
+}
@@ -0,0 +1,93 @@
+//! Integrity testing of lzip streams in the style of `lzip --test`.
+
+use std::io;
+use std::io::prelude::*;
+use std::io::BufReader;
+
+use bufread::decompress_step;
+use index::Member;
+use mem::{DecodeError, Decompress, Error, Status};
+
+/// The outcome of testing an lzip stream with `test`.
+#[derive(Debug, Clone)]
+pub struct Report {
+    /// The members that decoded correctly and whose trailers matched, in
+    /// the order they appear.
+    pub members: Vec<Member>,
+    /// Number of bytes of trailing data after the last member.
+    pub trailing_size: u64,
+    /// The first error found, if any. Testing stops at this error, so the
+    /// failing member is not part of `members`.
+    pub error: Option<DecodeError>,
+}
+
+impl Report {
+    /// Returns true if no error was found.
+    pub fn is_ok(&self) -> bool {
+        self.error.is_none()
+    }
+}
+
+/// Tests the integrity of the lzip stream read from `r`.
+///
+/// Every member is decoded and checked against the CRC, data size and member
+/// size stored in its trailer, without writing the decompressed data
+/// anywhere. Errors in the data are reported in the returned `Report`;
+/// errors reading from `r` are returned as is.
+///
+/// # Example
+///
+/// ```no_run
+/// use std::fs::File;
+///
+/// let report = lzip::test(File::open("backup.lz").unwrap()).unwrap();
+/// match report.error {
+///     Some(e) => println!("member {} at {}: {}", e.member, e.member_offset, e.error),
+///     None => println!("{} members ok", report.members.len()),
+/// }
+/// ```
+pub fn test<R: Read>(r: R) -> io::Result<Report> {
+    let mut r = BufReader::new(r);
+    let mut data = Decompress::new();
+    let mut out = vec![0; 64 * 1024];
+    let mut report = Report {
+        members: Vec::new(),
+        trailing_size: 0,
+        error: None,
+    };
+    let mut data_offset = 0;
+    let mut eof = false;
+    loop {
+        let (_, ret) = decompress_step(&mut r, &mut data, &mut eof, &mut out)?;
+        let error = match ret {
+            Ok(Status::MemberEnd) => {
+                let member = Member {
+                    member_offset: data.member_offset(),
+                    member_size: data.member_position(),
+                    data_offset,
+                    data_size: data.data_position(),
+                    data_crc: data.data_crc(),
+                    dictionary_size: data.dictionary_size(),
+                };
+                data_offset += member.data_size;
+                report.members.push(member);
+                continue;
+            }
+            Ok(Status::StreamEnd) => return Ok(report),
+            Ok(Status::MemNeeded) if eof => data.unexpected_eof(),
+            Ok(_) => continue,
+            Err(Error::DataMagic) if !report.members.is_empty() => {
+                let end = report
+                    .members
+                    .last()
+                    .map_or(0, |m| m.member_offset + m.member_size);
+                let rest = io::copy(&mut r, &mut io::sink())?;
+                report.trailing_size = data.total_in() + rest - end;
+                return Ok(report);
+            }
+            Err(e) => e,
+        };
+        report.error = Some(data.decode_error(report.members.len() as u64, error));
+        return Ok(report);
+    }
+}
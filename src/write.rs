@@ -0,0 +1,293 @@
+//! Writer-based compression streams
+
+use std::fs::File;
+use std::io;
+use std::io::prelude::*;
+use std::io::SeekFrom;
+
+#[cfg(feature = "tokio")]
+use futures::Poll;
+#[cfg(feature = "tokio")]
+use tokio_io::{AsyncRead, AsyncWrite};
+
+use bufread::error_kind;
+use index::Index;
+use mem::{Compress, Status};
+use verify;
+
+/// A compression stream which will have uncompressed data written to it and
+/// will write compressed data to an output stream.
+///
+/// All the data written is compressed into a single lzip member, which is
+/// completed by `finish` or `try_finish`, or when the encoder is dropped.
+///
+/// `flush` writes out the compressed data produced so far, but the data
+/// still buffered by the compressor is only written once the member is
+/// completed. Forcing it out would take a sync flush marker, and members
+/// with many of those fail the trailer consistency checks of `Index` and of
+/// lzip itself.
+pub struct LzEncoder<W: Write> {
+    data: Compress,
+    obj: Option<W>,
+    buf: Vec<u8>,
+    finishing: bool,
+    done: bool,
+}
+
+impl<W: Write> LzEncoder<W> {
+    /// Create a new compression stream which will compress at the given
+    /// level to write compress output to the give output stream.
+    pub fn new(obj: W, level: u32) -> LzEncoder<W> {
+        LzEncoder {
+            data: Compress::new(level),
+            obj: Some(obj),
+            buf: Vec::with_capacity(32 * 1024),
+            finishing: false,
+            done: false,
+        }
+    }
+
+    fn dump(&mut self) -> io::Result<()> {
+        while !self.buf.is_empty() {
+            let n = match self.obj.as_mut().unwrap().write(&self.buf) {
+                Ok(0) => return Err(io::ErrorKind::WriteZero.into()),
+                Ok(n) => n,
+                Err(ref err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                Err(err) => return Err(err),
+            };
+            self.buf.drain(..n);
+        }
+        Ok(())
+    }
+
+    /// Acquires a reference to the underlying writer.
+    pub fn get_ref(&self) -> &W {
+        self.obj.as_ref().unwrap()
+    }
+
+    /// Acquires a mutable reference to the underlying writer.
+    ///
+    /// Note that mutating the output/input state of the stream may corrupt
+    /// this object, so care must be taken when using this method.
+    pub fn get_mut(&mut self) -> &mut W {
+        self.obj.as_mut().unwrap()
+    }
+
+    /// Attempt to finish this output stream, writing out final chunks of data.
+    ///
+    /// Note that this function can only be used once data has finished being
+    /// written to the output stream. After this function is called then
+    /// further calls to `write` may result in a panic.
+    ///
+    /// # Panics
+    ///
+    /// Attempts to write data to this stream may result in a panic after this
+    /// function is called.
+    pub fn try_finish(&mut self) -> io::Result<()> {
+        while !self.done {
+            self.dump()?;
+            if !self.finishing {
+                self.data.finish()?;
+                self.finishing = true;
+            }
+            if self.data.compress_vec(&[], &mut self.buf)? == Status::StreamEnd {
+                self.done = true;
+            }
+        }
+        self.dump()
+    }
+
+    /// Consumes this encoder, flushing the output stream.
+    ///
+    /// This will flush the underlying data stream and then return the contained
+    /// writer if the flush succeeded.
+    ///
+    /// Note that this function may not be suitable to call in a situation where
+    /// the underlying stream is an asynchronous I/O stream. To finish a stream
+    /// the `try_finish` (or `shutdown`) method should be used instead. To
+    /// re-acquire ownership of a stream it is safe to call this method after
+    /// `try_finish` or `shutdown` has returned `Ok`.
+    pub fn finish(mut self) -> io::Result<W> {
+        self.try_finish()?;
+        Ok(self.obj.take().unwrap())
+    }
+
+    /// Returns the number of bytes produced by the compressor
+    ///
+    /// Note that, due to buffering, this only bears any relation to
+    /// `total_in()` once the stream is finished.  At that point,
+    /// `total_out() / total_in()` is the compression ratio.
+    pub fn total_out(&self) -> u64 {
+        self.data.total_out()
+    }
+
+    /// Returns the number of bytes consumed by the compressor
+    /// (e.g. the number of bytes written to this stream.)
+    pub fn total_in(&self) -> u64 {
+        self.data.total_in()
+    }
+}
+
+impl<W: Write> Write for LzEncoder<W> {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        loop {
+            self.dump()?;
+
+            let before = self.total_in();
+            self.data.compress_vec(data, &mut self.buf)?;
+            let written = (self.total_in() - before) as usize;
+
+            if written > 0 || data.is_empty() {
+                return Ok(written);
+            }
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if !self.finishing {
+            loop {
+                self.dump()?;
+                let before = self.total_out();
+                self.data.compress_vec(&[], &mut self.buf)?;
+                if before == self.total_out() {
+                    break;
+                }
+            }
+        }
+        self.dump()?;
+        self.obj.as_mut().unwrap().flush()
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl<W: AsyncWrite> AsyncWrite for LzEncoder<W> {
+    fn shutdown(&mut self) -> Poll<(), io::Error> {
+        self.try_finish()?;
+        self.get_mut().shutdown()
+    }
+}
+
+impl<W: Read + Write> Read for LzEncoder<W> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.get_mut().read(buf)
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl<W: AsyncRead + AsyncWrite> AsyncRead for LzEncoder<W> {}
+
+impl<W: Write> Drop for LzEncoder<W> {
+    fn drop(&mut self) {
+        if self.obj.is_some() {
+            let _ = self.try_finish();
+        }
+    }
+}
+
+/// Appends members to an existing lzip file.
+///
+/// The file is checked when the appender is created: its member headers and
+/// trailers must be consistent, and its last member must decompress to the
+/// data described by its trailer. New members are then written after the
+/// last one, each through an `LzEncoder`.
+///
+/// # Example
+///
+/// ```no_run
+/// use std::fs::OpenOptions;
+/// use std::io::prelude::*;
+/// use lzip::write::LzAppender;
+///
+/// let file = OpenOptions::new().read(true).write(true).open("app.log.lz").unwrap();
+/// let mut appender = LzAppender::new(file).unwrap();
+/// appender.truncate_trailing_data().unwrap();
+/// let mut encoder = appender.append_member(6).unwrap();
+/// encoder.write_all(b"rotated log lines\n").unwrap();
+/// encoder.finish().unwrap();
+/// ```
+pub struct LzAppender {
+    file: File,
+    end: u64,
+    file_size: u64,
+}
+
+impl LzAppender {
+    /// Opens the lzip file `file` for appending.
+    ///
+    /// `file` must be open for both reading and writing. An empty file is
+    /// accepted and gets its first member on the first append. Trailing data
+    /// after the last member is not an error here, see
+    /// `truncate_trailing_data`.
+    pub fn new(mut file: File) -> io::Result<LzAppender> {
+        let file_size = file.seek(SeekFrom::End(0))?;
+        if file_size == 0 {
+            return Ok(LzAppender {
+                file,
+                end: 0,
+                file_size,
+            });
+        }
+
+        let index = Index::new(&mut file)?;
+        if let Some(last) = index.members().last() {
+            file.seek(SeekFrom::Start(last.member_offset))?;
+            let report = verify::test((&mut file).take(last.member_size))?;
+            if let Some(mut e) = report.error {
+                // The positions are relative to the last member.
+                e.member = index.members().len() as u64 - 1;
+                e.member_offset += last.member_offset;
+                e.pos += last.member_offset;
+                e.total_out += last.data_offset;
+                return Err(io::Error::new(error_kind(e.error), e));
+            }
+        }
+        Ok(LzAppender {
+            file,
+            end: index.compressed_size(),
+            file_size,
+        })
+    }
+
+    /// Returns the number of bytes of trailing data after the last member.
+    pub fn trailing_size(&self) -> u64 {
+        self.file_size - self.end
+    }
+
+    /// Removes any trailing data after the last member from the file.
+    pub fn truncate_trailing_data(&mut self) -> io::Result<()> {
+        if self.file_size != self.end {
+            self.file.set_len(self.end)?;
+            self.file_size = self.end;
+        }
+        Ok(())
+    }
+
+    /// Starts a new member after the last one, compressed at `level`.
+    ///
+    /// The member is complete once the returned encoder is finished or
+    /// dropped, after which further members may be appended. This fails if
+    /// the file has trailing data, as members written after it would be
+    /// taken as part of that data.
+    pub fn append_member(&mut self, level: u32) -> io::Result<LzEncoder<&mut File>> {
+        if self.trailing_size() != 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "lzip: can't append members after trailing data",
+            ));
+        }
+        // Only whole members are written after `end`, so the file always
+        // ends with the last member here.
+        self.file.seek(SeekFrom::End(0))?;
+        Ok(LzEncoder::new(&mut self.file, level))
+    }
+
+    /// Acquires a reference to the underlying file.
+    pub fn get_ref(&self) -> &File {
+        &self.file
+    }
+
+    /// Consumes this appender, returning the underlying file.
+    pub fn into_inner(self) -> File {
+        self.file
+    }
+}
@@ -0,0 +1,38 @@
+extern crate lzip;
+
+use std::fs;
+use std::io::Cursor;
+
+fn testsuite(name: &str) -> Vec<u8> {
+    let dir = concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/lzip-sys/lzlib-1.13/testsuite/"
+    );
+    fs::read(format!("{}{}", dir, name)).unwrap()
+}
+
+const FOX: &[u8] = b"The quick brown fox jumps over the lazy dog.\n";
+
+#[test]
+fn carve_finds_members_in_garbage() {
+    let fox = testsuite("fox.lz");
+    let text = testsuite("test.txt.lz");
+    let mut image = b"LZIP garbage before".to_vec();
+    let a = image.len() as u64;
+    image.extend_from_slice(&text);
+    image.extend_from_slice(&[0; 1000]);
+    let b = image.len() as u64;
+    image.extend_from_slice(&fox);
+    // A member cut short is not valid.
+    image.extend_from_slice(&fox[..50]);
+
+    let members = lzip::carve(&mut Cursor::new(&image)).unwrap();
+    assert_eq!(members.len(), 2);
+    assert_eq!(members[0].member_offset, a);
+    assert_eq!(members[0].member_size, text.len() as u64);
+    assert_eq!(members[0].data_offset, 0);
+    assert_eq!(members[1].member_offset, b);
+    assert_eq!(members[1].member_size, fox.len() as u64);
+    assert_eq!(members[1].data_offset, members[0].data_size);
+    assert_eq!(members[1].data_size, FOX.len() as u64);
+}
@@ -0,0 +1,89 @@
+extern crate lzip;
+
+use std::fs;
+use std::io;
+use std::io::prelude::*;
+use std::io::Cursor;
+
+use lzip::format::Error as FormatError;
+use lzip::read::MultiLzDecoder;
+use lzip::{ConcatError, Error};
+
+fn testsuite(name: &str) -> Vec<u8> {
+    let dir = concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/lzip-sys/lzlib-1.13/testsuite/"
+    );
+    fs::read(format!("{}{}", dir, name)).unwrap()
+}
+
+fn concat(inputs: &[Vec<u8>], output: &mut Vec<u8>) -> io::Result<u64> {
+    let mut inputs: Vec<_> = inputs.iter().map(Cursor::new).collect();
+    lzip::concat(&mut inputs, output)
+}
+
+fn concat_error(inputs: &[Vec<u8>]) -> ConcatError {
+    let mut out = Vec::new();
+    let e = concat(inputs, &mut out).unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+    // Nothing is written unless all inputs are fine.
+    assert!(out.is_empty());
+    *e.into_inner().unwrap().downcast::<ConcatError>().unwrap()
+}
+
+#[test]
+fn members_are_copied() {
+    let names = ["fox.lz", "test.txt.lz", "test_em.txt.lz", "test_sync.lz"];
+    let inputs: Vec<_> = names.iter().map(|name| testsuite(name)).collect();
+    let mut out = Vec::new();
+    let n = concat(&inputs, &mut out).unwrap();
+    assert_eq!(out, inputs.concat());
+    assert_eq!(n, out.len() as u64);
+
+    let mut data = Vec::new();
+    MultiLzDecoder::new(&out[..])
+        .read_to_end(&mut data)
+        .unwrap();
+    let text = testsuite("test.txt");
+    let mut expected = b"The quick brown fox jumps over the lazy dog.\n".to_vec();
+    expected.extend_from_slice(&text);
+    expected.extend_from_slice(&text);
+    expected.extend_from_slice(&text);
+    assert_eq!(data, expected);
+
+    // No inputs make no output.
+    assert_eq!(concat(&[], &mut Vec::new()).unwrap(), 0);
+}
+
+#[test]
+fn damaged_input_is_rejected() {
+    let fox = testsuite("fox.lz");
+    let e = concat_error(&[fox.clone(), fox, testsuite("fox_bcrc.lz")]);
+    match e {
+        ConcatError::Damaged { input, error } => {
+            assert_eq!(input, 2);
+            assert_eq!(error.member, 0);
+            assert!(matches!(
+                error.error,
+                Error::Format(FormatError::CrcMismatch { .. })
+            ));
+        }
+        other => panic!("unexpected error {:?}", other),
+    }
+}
+
+#[test]
+fn trailing_data_and_garbage_are_rejected() {
+    let fox = testsuite("fox.lz");
+    let mut trailing = fox.clone();
+    trailing.extend_from_slice(b"trailing data");
+    assert_eq!(
+        concat_error(&[fox.clone(), trailing]),
+        ConcatError::TrailingData { input: 1, size: 13 }
+    );
+
+    match concat_error(&[b"not an lzip file at all".to_vec(), fox]) {
+        ConcatError::Invalid { input, .. } => assert_eq!(input, 0),
+        other => panic!("unexpected error {:?}", other),
+    }
+}
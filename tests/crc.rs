@@ -0,0 +1,97 @@
+extern crate lzip;
+
+use lzip::Crc32;
+
+/// Bit-at-a-time CRC32, independent of the table-driven and SIMD code.
+fn reference(crc: u32, buf: &[u8]) -> u32 {
+    let mut crc = !crc;
+    for &b in buf {
+        crc ^= b as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                0xEDB8_8320 ^ (crc >> 1)
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+/// Returns `len` bytes of reproducible pseudo-random data.
+fn data(len: usize) -> Vec<u8> {
+    let mut x = 0x2545_F491_4F6C_DD1Du64;
+    (0..len)
+        .map(|_| {
+            x ^= x << 13;
+            x ^= x >> 7;
+            x ^= x << 17;
+            (x >> 24) as u8
+        })
+        .collect()
+}
+
+fn crc(buf: &[u8]) -> u32 {
+    let mut crc = Crc32::new();
+    crc.update(buf);
+    crc.sum()
+}
+
+#[test]
+fn update_matches_reference() {
+    let buf = data(4096 + 16);
+    let mut lengths: Vec<usize> = (0..300).collect();
+    lengths.extend_from_slice(&[511, 512, 513, 1000, 1024, 2047, 4095, 4096]);
+    // Unaligned starts reach the loads of the SIMD paths at every offset.
+    for start in 0..16 {
+        for &len in &lengths {
+            let slice = &buf[start..start + len];
+            assert_eq!(
+                crc(slice),
+                reference(0, slice),
+                "{} bytes at {}",
+                len,
+                start
+            );
+        }
+    }
+}
+
+#[test]
+fn update_continues_from_previous_data() {
+    let buf = data(3000);
+    let expected = reference(0, &buf);
+    for &step in &[1, 15, 16, 63, 64, 127, 128, 129, 1000] {
+        let mut crc = Crc32::new();
+        for chunk in buf.chunks(step) {
+            crc.update(chunk);
+        }
+        assert_eq!(crc.sum(), expected, "chunks of {}", step);
+        assert_eq!(crc.amount(), buf.len() as u64);
+
+        let mut resumed = Crc32::from_value(reference(0, &buf[..step]), step as u64);
+        resumed.update(&buf[step..]);
+        assert_eq!(resumed.sum(), expected, "resumed after {}", step);
+    }
+}
+
+#[test]
+fn combine_matches_update_of_joined_data() {
+    let buf = data(5000);
+    let expected = reference(0, &buf);
+    for &split in &[0, 1, 7, 128, 129, 2500, 4999, 5000] {
+        let (a, b) = buf.split_at(split);
+        let mut head = Crc32::new();
+        head.update(a);
+        let mut tail = Crc32::new();
+        tail.update(b);
+        head.combine(&tail);
+        assert_eq!(head.sum(), expected, "split at {}", split);
+        assert_eq!(head.amount(), buf.len() as u64);
+    }
+
+    // Both halves empty.
+    let mut empty = Crc32::new();
+    empty.combine(&Crc32::new());
+    assert_eq!(empty, Crc32::new());
+}
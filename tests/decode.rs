@@ -0,0 +1,323 @@
+extern crate lzip;
+
+use std::fs;
+use std::io;
+use std::io::prelude::*;
+
+use lzip::bufread::MultiLzDecoder;
+use lzip::format::{Error as FormatError, Header, Trailer, TRAILER_SIZE};
+use lzip::parser::{Event, Parser};
+use lzip::{DecodeError, Error, Truncation};
+
+fn testsuite(name: &str) -> Vec<u8> {
+    let dir = concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/lzip-sys/lzlib-1.13/testsuite/"
+    );
+    fs::read(format!("{}{}", dir, name)).unwrap()
+}
+
+fn decode_error(e: &io::Error) -> DecodeError {
+    *e.get_ref().unwrap().downcast_ref::<DecodeError>().unwrap()
+}
+
+/// Reads `r` in chunks of `size` bytes until it fails, returning the data
+/// read before the error.
+fn read_until_error<R: Read>(mut r: R, size: usize) -> (Vec<u8>, io::Error) {
+    let mut out = Vec::new();
+    let mut buf = vec![0; size];
+    loop {
+        match r.read(&mut buf) {
+            Ok(0) => panic!("no error"),
+            Ok(n) => out.extend_from_slice(&buf[..n]),
+            Err(e) => return (out, e),
+        }
+    }
+}
+
+#[test]
+fn data_before_error_is_returned() {
+    let fox = b"The quick brown fox jumps over the lazy dog.\n";
+    let damaged = testsuite("fox_bcrc.lz");
+    for &size in &[1, 8, 64, 4096] {
+        let r = MultiLzDecoder::new(&damaged[..]);
+        let (out, e) = read_until_error(r, size);
+        assert_eq!(out, &fox[..], "reading {} bytes at a time", size);
+        assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+        let e = decode_error(&e);
+        assert_eq!(e.total_out, fox.len() as u64);
+        assert!(matches!(e.error, Error::Format(_)));
+    }
+
+    let mut parser = Parser::new(&damaged[..]);
+    let mut out = Vec::new();
+    let e = loop {
+        match parser.next_event() {
+            Ok(Some(Event::Data(data))) => out.extend_from_slice(data),
+            Ok(Some(_)) => {}
+            Ok(None) => panic!("no error"),
+            Err(e) => break e,
+        }
+    };
+    assert_eq!(out, &fox[..]);
+    assert_eq!(decode_error(&e).total_out, fox.len() as u64);
+}
+
+#[test]
+fn members_return_data_before_error() {
+    let fox = b"The quick brown fox jumps over the lazy dog.\n";
+    let damaged = testsuite("fox_bcrc.lz");
+    let mut members = lzip::bufread::Members::new(&damaged[..]);
+    let member = members.next_member().unwrap().unwrap();
+    let (out, e) = read_until_error(member, 8);
+    assert_eq!(out, &fox[..]);
+    assert_eq!(decode_error(&e).total_out, fox.len() as u64);
+}
+
+#[test]
+fn dictionary_too_large_reports_its_member() {
+    let fox = testsuite("fox.lz");
+    let mut lz = fox.clone();
+    lz.extend_from_slice(&testsuite("test.txt.lz"));
+    for &size in &[1, 3, 7, 64, 4096] {
+        let r = io::BufReader::with_capacity(size, &lz[..]);
+        let mut decoder = MultiLzDecoder::new(r).max_dictionary_size(1 << 12);
+        let e = decoder.read_to_end(&mut Vec::new()).unwrap_err();
+        let e = decode_error(&e);
+        assert_eq!(e.member, 1, "reading {} bytes at a time", size);
+        assert_eq!(e.member_offset, fox.len() as u64);
+        assert_eq!(e.pos, fox.len() as u64 + 5);
+        assert!(matches!(e.error, Error::DictionaryTooLarge { .. }));
+    }
+}
+
+#[test]
+fn test_reports_decode_error() {
+    let mut lz = testsuite("test.txt.lz");
+    let first = lz.len() as u64;
+    lz.extend_from_slice(&testsuite("fox_de20.lz"));
+    let report = lzip::test(&lz[..]).unwrap();
+    assert_eq!(report.members.len(), 1);
+    let e = report.error.unwrap();
+    assert_eq!(e.member, 1);
+    assert_eq!(e.member_offset, first);
+    assert!(e.pos > first);
+    assert_eq!(e.error, Error::Data);
+}
+
+#[test]
+fn output_limit_on_damaged_input() {
+    // The limits are reached exactly where the damage is found, which must
+    // still be reported.
+    let damaged = testsuite("fox_bcrc.lz");
+    let decoder = MultiLzDecoder::new(&damaged[..]).max_output_size(45);
+    let (out, e) = read_until_error(decoder, 64);
+    assert_eq!(out.len(), 45);
+    let e = decode_error(&e).error;
+    assert!(matches!(e, Error::Format(FormatError::CrcMismatch { .. })));
+
+    let damaged = testsuite("fox_de20.lz");
+    let decoder = MultiLzDecoder::new(&damaged[..]).max_output_size(17);
+    let (out, e) = read_until_error(decoder, 64);
+    assert_eq!(out.len(), 17);
+    assert_eq!(decode_error(&e).error, Error::Data);
+}
+
+#[test]
+fn output_limit_with_trailing_data() {
+    let fox = b"The quick brown fox jumps over the lazy dog.\n";
+    let mut lz = testsuite("fox.lz");
+    lz.extend_from_slice(b"trailing data");
+    let mut out = Vec::new();
+    MultiLzDecoder::new(&lz[..])
+        .max_output_size(fox.len() as u64)
+        .read_to_end(&mut out)
+        .unwrap();
+    assert_eq!(out, &fox[..]);
+
+    let decoder = MultiLzDecoder::new(&lz[..]).max_output_size(fox.len() as u64 - 1);
+    let (out, e) = read_until_error(decoder, 64);
+    assert_eq!(out, &fox[..fox.len() - 1]);
+    let expected = Error::OutputTooLarge {
+        produced: fox.len() as u64 - 1,
+        limit: fox.len() as u64 - 1,
+    };
+    assert_eq!(decode_error(&e).error, expected);
+}
+
+#[test]
+fn ratio_limit() {
+    let lz = testsuite("test.txt.lz");
+    let text = testsuite("test.txt");
+    // test.txt expands by a factor of almost 5.
+    let mut out = Vec::new();
+    MultiLzDecoder::new(&lz[..])
+        .max_ratio(5)
+        .read_to_end(&mut out)
+        .unwrap();
+    assert_eq!(out, text);
+
+    let decoder = MultiLzDecoder::new(&lz[..]).max_ratio(4);
+    let (out, e) = read_until_error(decoder, 4096);
+    assert_eq!(out, &text[..out.len()]);
+    let expected = Error::RatioTooLarge {
+        produced: 4 * lz.len() as u64,
+        consumed: lz.len() as u64,
+        limit: 4,
+    };
+    assert_eq!(decode_error(&e).error, expected);
+}
+
+/// Decodes `name` from the testsuite, returning the error it fails with.
+fn failure(name: &str) -> DecodeError {
+    let lz = testsuite(name);
+    let e = MultiLzDecoder::new(&lz[..])
+        .read_to_end(&mut Vec::new())
+        .unwrap_err();
+    let e = decode_error(&e);
+    assert_eq!(lzip::test(&lz[..]).unwrap().error, Some(e), "{}", name);
+    e
+}
+
+#[test]
+fn trailer_mismatches() {
+    let fox = b"The quick brown fox jumps over the lazy dog.\n";
+    let mut crc = lzip::Crc32::new();
+    crc.update(fox);
+    let crc = crc.sum();
+    let size = testsuite("fox.lz").len() as u64;
+
+    let cases = [
+        (
+            "fox_bcrc.lz",
+            FormatError::CrcMismatch {
+                stored: crc ^ 1,
+                computed: crc,
+            },
+        ),
+        // The CRC is checked first, so a zeroed CRC is reported as such even
+        // though the data size is still right.
+        (
+            "fox_crc0.lz",
+            FormatError::CrcMismatch {
+                stored: 0,
+                computed: crc,
+            },
+        ),
+        (
+            "fox_das46.lz",
+            FormatError::DataSizeMismatch {
+                stored: 46,
+                computed: 45,
+            },
+        ),
+    ];
+    for &(name, expected) in &cases {
+        let lz = testsuite(name);
+        let trailer = Trailer::parse(&lz[lz.len() - TRAILER_SIZE..]).unwrap();
+        assert_eq!(
+            trailer.validate(crc, fox.len() as u64, size),
+            Err(expected),
+            "{}",
+            name
+        );
+
+        let e = failure(name);
+        assert_eq!(e.error, Error::Format(expected), "{}", name);
+        assert_eq!(e.pos, size, "{}", name);
+        assert_eq!(e.total_out, fox.len() as u64, "{}", name);
+    }
+}
+
+#[test]
+fn header_and_data_diagnostics() {
+    let e = failure("fox_v2.lz");
+    assert_eq!(e.error, Error::Format(FormatError::UnsupportedVersion(2)));
+    assert_eq!((e.pos, e.total_out), (4, 0));
+    let lz = testsuite("fox_v2.lz");
+    assert_eq!(
+        Header::parse(&lz).unwrap_err(),
+        FormatError::UnsupportedVersion(2)
+    );
+
+    let e = failure("fox_s11.lz");
+    assert_eq!(
+        e.error,
+        Error::Format(FormatError::InvalidDictionarySize(2048))
+    );
+    assert_eq!((e.pos, e.total_out), (5, 0));
+    let lz = testsuite("fox_s11.lz");
+    assert_eq!(
+        Header::parse(&lz).unwrap_err(),
+        FormatError::InvalidDictionarySize(2048)
+    );
+
+    let size = testsuite("fox.lz").len() as u64;
+    let e = failure("fox_mes81.lz");
+    let expected = FormatError::MemberSizeMismatch {
+        stored: size + 1,
+        computed: size,
+    };
+    assert_eq!(e.error, Error::Format(expected));
+    assert_eq!((e.pos, e.total_out), (size, 45));
+
+    // The damage in the compressed data is only found some bytes after it.
+    let e = failure("fox_de20.lz");
+    assert_eq!(e.error, Error::Data);
+    assert_eq!((e.member, e.member_offset), (0, 0));
+    assert_eq!((e.pos, e.total_out), (33, 17));
+}
+
+#[test]
+fn truncation_reports_the_part_cut_short() {
+    let fox = b"The quick brown fox jumps over the lazy dog.\n";
+    let lz = testsuite("fox.lz");
+    let cases = [
+        (3, Truncation::Header),
+        (40, Truncation::Data),
+        (lz.len() - 10, Truncation::Trailer),
+    ];
+    for &(cut, part) in &cases {
+        let (out, e) = read_until_error(MultiLzDecoder::new(&lz[..cut]), 64);
+        assert_eq!(e.kind(), io::ErrorKind::UnexpectedEof);
+        let e = decode_error(&e);
+        let produced = out.len() as u64;
+        assert_eq!(
+            e.error,
+            Error::UnexpectedEof { part, produced },
+            "cut at {}",
+            cut
+        );
+        assert_eq!(e.total_out, produced);
+        assert_eq!(out, &fox[..out.len()]);
+        // All the data is there once the trailer has been reached.
+        match part {
+            Truncation::Header => assert_eq!(produced, 0),
+            Truncation::Data => assert!(produced < fox.len() as u64),
+            Truncation::Trailer => assert_eq!(produced, fox.len() as u64),
+        }
+        assert_eq!(lzip::test(&lz[..cut]).unwrap().error, Some(e));
+    }
+}
+
+#[test]
+fn members_keep_the_header_read() {
+    // Fraction bits are ignored for the smallest dictionary size, so this
+    // header codes the same size as fox.lz in a different way.
+    let mut lz = testsuite("fox.lz");
+    lz[5] = 0x2c;
+    for &size in &[1, 3, 7, 4096] {
+        let r = io::BufReader::with_capacity(size, &lz[..]);
+        let mut members = lzip::bufread::Members::new(r);
+        let mut member = members.next_member().unwrap().unwrap();
+        assert_eq!(
+            member.header().to_bytes(),
+            lz[..6],
+            "{} bytes at a time",
+            size
+        );
+        assert_eq!(member.header().dictionary_size(), 1 << 12);
+        io::copy(&mut member, &mut io::sink()).unwrap();
+        assert!(members.next_member().unwrap().is_none());
+    }
+}
@@ -0,0 +1,99 @@
+extern crate lzip;
+
+use std::fs;
+use std::io;
+use std::io::Cursor;
+use std::path::PathBuf;
+use std::process;
+
+use lzip::Selection;
+
+fn testsuite(name: &str) -> Vec<u8> {
+    let dir = concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/lzip-sys/lzlib-1.13/testsuite/"
+    );
+    fs::read(format!("{}{}", dir, name)).unwrap()
+}
+
+const FOX: &[u8] = b"The quick brown fox jumps over the lazy dog.\n";
+
+/// Returns a file made of fox.lz, test.txt.lz and fox.lz again, along with
+/// its decompressed data.
+fn three_members() -> (Vec<u8>, Vec<u8>) {
+    let fox = testsuite("fox.lz");
+    let mut lz = fox.clone();
+    lz.extend_from_slice(&testsuite("test.txt.lz"));
+    lz.extend_from_slice(&fox);
+    let mut data = FOX.to_vec();
+    data.extend_from_slice(&testsuite("test.txt"));
+    data.extend_from_slice(FOX);
+    (lz, data)
+}
+
+/// A path in the temporary directory unique to this process and `name`.
+fn temp_path(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("lzip-tests-{}-{}", process::id(), name));
+    fs::create_dir_all(&dir).unwrap();
+    dir.join("file.lz")
+}
+
+#[test]
+fn dump_strip_and_remove() {
+    let fox = testsuite("fox.lz");
+    let text = testsuite("test.txt.lz");
+    let (mut lz, _) = three_members();
+    lz.extend_from_slice(b"trailing data");
+
+    let mut out = Vec::new();
+    let selection = Selection::new().member(1).trailing_data();
+    lzip::dump(&mut Cursor::new(&lz), &selection, &mut out).unwrap();
+    let mut expected = text.clone();
+    expected.extend_from_slice(b"trailing data");
+    assert_eq!(out, expected);
+
+    let mut out = Vec::new();
+    lzip::strip(&mut Cursor::new(&lz), &selection, &mut out).unwrap();
+    let mut expected = fox.clone();
+    expected.extend_from_slice(&fox);
+    assert_eq!(out, expected);
+
+    let path = temp_path("remove");
+    fs::write(&path, &lz).unwrap();
+    let mut file = fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(&path)
+        .unwrap();
+    let size = lzip::remove(&mut file, &selection).unwrap();
+    assert_eq!(size, expected.len() as u64);
+    assert_eq!(fs::read(&path).unwrap(), expected);
+    fs::remove_dir_all(path.parent().unwrap()).unwrap();
+}
+
+#[test]
+fn dump_damaged_members() {
+    let (mut lz, _) = three_members();
+    let fox = testsuite("fox.lz");
+    lz[fox.len() + 2000] ^= 0x55;
+
+    let mut out = Vec::new();
+    let selection = Selection::new().damaged();
+    lzip::dump(&mut Cursor::new(&lz), &selection, &mut out).unwrap();
+    assert_eq!(out, &lz[fox.len()..lz.len() - fox.len()]);
+
+    // Removing every member is refused.
+    let selection = Selection::new().member(0).member(1).member(2);
+    let e = lzip::strip(&mut Cursor::new(&lz), &selection, &mut Vec::new()).unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::InvalidInput);
+}
+
+#[test]
+fn selecting_missing_member_fails() {
+    let (lz, _) = three_members();
+    let selection = Selection::new().member(7);
+    let e = lzip::strip(&mut Cursor::new(&lz), &selection, &mut Vec::new()).unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::InvalidInput);
+    let e = lzip::dump(&mut Cursor::new(&lz), &selection, &mut Vec::new()).unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::InvalidInput);
+}
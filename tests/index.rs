@@ -0,0 +1,32 @@
+extern crate lzip;
+
+use std::fs;
+use std::io::Cursor;
+
+fn testsuite(name: &str) -> Vec<u8> {
+    let dir = concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/lzip-sys/lzlib-1.13/testsuite/"
+    );
+    fs::read(format!("{}{}", dir, name)).unwrap()
+}
+
+#[test]
+fn trailing_data_resembling_a_header() {
+    let fox = testsuite("fox.lz");
+    let index_of = |trailing: &[u8]| {
+        let mut lz = fox.clone();
+        lz.extend_from_slice(trailing);
+        lzip::index::Index::new(&mut Cursor::new(lz))
+    };
+
+    // Not a valid header, so it is only trailing data.
+    let index = index_of(b"LZIPxx").unwrap();
+    assert_eq!(index.members().len(), 1);
+    assert_eq!(index.trailing_size(), 6);
+
+    // The start of a header or a whole one means a truncated member.
+    for trailing in &[&b"LZ"[..], b"LZIP\x01", b"LZIP\x01\x0c"] {
+        assert!(index_of(trailing).is_err());
+    }
+}
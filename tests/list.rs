@@ -0,0 +1,86 @@
+extern crate lzip;
+
+use std::fs;
+use std::io::Cursor;
+
+use lzip::Summary;
+
+fn testsuite(name: &str) -> Vec<u8> {
+    let dir = concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/lzip-sys/lzlib-1.13/testsuite/"
+    );
+    fs::read(format!("{}{}", dir, name)).unwrap()
+}
+
+fn list(lz: &[u8]) -> Summary {
+    lzip::list(&mut Cursor::new(lz)).unwrap()
+}
+
+/// Checks `summary` against the members found by decompressing `lz`.
+fn check_against_test(lz: &[u8], summary: &Summary) {
+    let report = lzip::test(lz).unwrap();
+    assert!(report.error.is_none());
+    let members = &report.members;
+    assert_eq!(summary.members, members.len());
+    assert_eq!(summary.data_size, members.iter().map(|m| m.data_size).sum());
+    assert_eq!(
+        summary.compressed_size,
+        members.iter().map(|m| m.member_size).sum()
+    );
+    let dictionary_size = members.iter().map(|m| m.dictionary_size).max();
+    assert_eq!(summary.dictionary_size, dictionary_size.unwrap());
+    assert_eq!(summary.trailing_size, report.trailing_size);
+}
+
+#[test]
+fn single_members() {
+    let text = testsuite("test.txt");
+    for name in &["test.txt.lz", "test_sync.lz"] {
+        let lz = testsuite(name);
+        let summary = list(&lz);
+        assert_eq!(summary.members, 1);
+        assert_eq!(summary.data_size, text.len() as u64);
+        assert_eq!(summary.compressed_size, lz.len() as u64);
+        assert_eq!(summary.trailing_size, 0);
+        check_against_test(&lz, &summary);
+    }
+
+    // fox.lz is larger than its data.
+    let summary = list(&testsuite("fox.lz"));
+    assert_eq!(summary.data_size, 45);
+    assert_eq!(summary.compressed_size, 80);
+    assert!(summary.saved() < 0.0);
+}
+
+#[test]
+fn multiple_members() {
+    // Empty members between the others.
+    let lz = testsuite("test_em.txt.lz");
+    let summary = list(&lz);
+    assert_eq!(summary.members, 8);
+    assert_eq!(summary.data_size, testsuite("test.txt").len() as u64);
+    assert_eq!(summary.compressed_size, lz.len() as u64);
+    check_against_test(&lz, &summary);
+
+    let mut lz = testsuite("fox.lz");
+    lz.extend_from_slice(&testsuite("test_em.txt.lz"));
+    lz.extend_from_slice(&testsuite("test_sync.lz"));
+    let summary = list(&lz);
+    assert_eq!(summary.members, 10);
+    assert_eq!(summary.data_size, 45 + 2 * 36_388);
+    assert_eq!(summary.compressed_size, lz.len() as u64);
+    check_against_test(&lz, &summary);
+}
+
+#[test]
+fn trailing_data() {
+    let mut lz = testsuite("test_em.txt.lz");
+    let size = lz.len() as u64;
+    lz.extend_from_slice(b"trailing data");
+    let summary = list(&lz);
+    assert_eq!(summary.members, 8);
+    assert_eq!(summary.compressed_size, size);
+    assert_eq!(summary.trailing_size, 13);
+    check_against_test(&lz, &summary);
+}
@@ -0,0 +1,164 @@
+extern crate lzip;
+
+use std::fs;
+use std::io::BufReader;
+
+use lzip::parser::{Event, Parser};
+
+fn testsuite(name: &str) -> Vec<u8> {
+    let dir = concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/lzip-sys/lzlib-1.13/testsuite/"
+    );
+    fs::read(format!("{}{}", dir, name)).unwrap()
+}
+
+/// A parser event with the data of consecutive `Data` events joined.
+#[derive(PartialEq, Eq, Debug, Clone)]
+enum Joined {
+    MemberStart(u32),
+    Data(Vec<u8>),
+    SyncPoint,
+    MemberEnd(lzip::format::Trailer),
+    TrailingData(u64),
+}
+
+fn events<R: std::io::BufRead>(mut parser: Parser<R>) -> Vec<Joined> {
+    let mut events = Vec::new();
+    while let Some(event) = parser.next_event().unwrap() {
+        match event {
+            Event::MemberStart(header) => {
+                events.push(Joined::MemberStart(header.dictionary_size()))
+            }
+            Event::Data(data) => match events.last_mut() {
+                Some(Joined::Data(joined)) => joined.extend_from_slice(data),
+                _ => events.push(Joined::Data(data.to_vec())),
+            },
+            Event::SyncPoint => events.push(Joined::SyncPoint),
+            Event::MemberEnd(trailer) => events.push(Joined::MemberEnd(trailer)),
+            Event::TrailingData(pos) => events.push(Joined::TrailingData(pos)),
+        }
+    }
+    events
+}
+
+#[test]
+fn sync_flushed_member() {
+    let lz = testsuite("test_sync.lz");
+    let text = testsuite("test.txt");
+    let whole = events(Parser::new(&lz[..]));
+    assert_eq!(whole.len(), 3);
+    assert!(matches!(whole[0], Joined::MemberStart(_)));
+    assert_eq!(whole[1], Joined::Data(text.clone()));
+    match whole[2] {
+        Joined::MemberEnd(trailer) => {
+            assert_eq!(trailer.data_size, text.len() as u64);
+            assert_eq!(trailer.member_size, lz.len() as u64);
+        }
+        ref e => panic!("unexpected event {:?}", e),
+    }
+
+    // The events don't depend on how the input is split up.
+    for &size in &[1, 3, 100] {
+        let parser = Parser::new(BufReader::with_capacity(size, &lz[..]));
+        assert_eq!(events(parser), whole);
+    }
+
+    // The file was flushed every 4096 bytes, each time with two markers.
+    let mut expected = vec![whole[0].clone()];
+    for (i, chunk) in text.chunks(4096).enumerate() {
+        if i > 0 {
+            expected.extend_from_slice(&[Joined::SyncPoint, Joined::SyncPoint]);
+        }
+        expected.push(Joined::Data(chunk.to_vec()));
+    }
+    expected.push(whole[2].clone());
+    for &size in &[1, 3, 100, 64 * 1024] {
+        let parser = Parser::new(BufReader::with_capacity(size, &lz[..])).sync_points(true);
+        assert_eq!(events(parser), expected, "reading {} bytes at a time", size);
+    }
+
+    // Members without markers have no sync points.
+    let lz = testsuite("test.txt.lz");
+    let parser = Parser::new(&lz[..]).sync_points(true);
+    assert!(!events(parser).contains(&Joined::SyncPoint));
+}
+
+#[test]
+fn sync_points_in_damaged_member() {
+    // Data decoded before the damage is still returned.
+    let lz = testsuite("test_sync.lz");
+    let text = testsuite("test.txt");
+    for &cut in &[20, 1000, 5000, lz.len() - 10] {
+        let mut parser = Parser::new(&lz[..cut]).sync_points(true);
+        let mut out = Vec::new();
+        let mut sync_points = 0;
+        let e = loop {
+            match parser.next_event() {
+                Ok(Some(Event::Data(data))) => out.extend_from_slice(data),
+                Ok(Some(Event::SyncPoint)) => {
+                    assert_eq!(out.len() % 4096, 0);
+                    sync_points += 1;
+                }
+                Ok(Some(_)) => {}
+                Ok(None) => panic!("no error"),
+                Err(e) => break e,
+            }
+        };
+        assert_eq!(e.kind(), std::io::ErrorKind::UnexpectedEof);
+        assert_eq!(out, &text[..out.len()]);
+        assert_eq!(parser.total_out(), out.len() as u64);
+        assert!(sync_points <= 2 * (out.len() / 4096));
+
+        let mut plain = Parser::new(&lz[..cut]);
+        let mut plain_out = Vec::new();
+        while let Ok(Some(event)) = plain.next_event() {
+            if let Event::Data(data) = event {
+                plain_out.extend_from_slice(data);
+            }
+        }
+        assert_eq!(out, plain_out, "cut at {}", cut);
+    }
+}
+
+#[test]
+fn members_and_trailing_data() {
+    let fox = testsuite("fox.lz");
+    let mut lz = testsuite("test.txt.lz");
+    lz.extend_from_slice(&fox);
+    lz.extend_from_slice(b"trailing data");
+    let events = events(Parser::new(BufReader::with_capacity(7, &lz[..])));
+    let kinds: Vec<_> = events
+        .iter()
+        .map(|e| match e {
+            Joined::MemberStart(_) => "start",
+            Joined::Data(_) => "data",
+            Joined::SyncPoint => "sync",
+            Joined::MemberEnd(_) => "end",
+            Joined::TrailingData(_) => "trailing",
+        })
+        .collect();
+    assert_eq!(
+        kinds,
+        ["start", "data", "end", "start", "data", "end", "trailing"]
+    );
+    assert_eq!(
+        events[6],
+        Joined::TrailingData((lz.len() - b"trailing data".len()) as u64)
+    );
+}
+
+#[test]
+fn member_start_reports_the_header_read() {
+    // Fraction bits are ignored for the smallest dictionary size, so this
+    // header codes the same size as fox.lz in a different way.
+    let mut lz = testsuite("fox.lz");
+    lz[5] = 0x2c;
+    for &size in &[1, 3, 4096] {
+        let mut parser = Parser::new(BufReader::with_capacity(size, &lz[..]));
+        match parser.next_event().unwrap() {
+            Some(Event::MemberStart(header)) => assert_eq!(header.to_bytes(), lz[..6]),
+            e => panic!("unexpected event {:?}", e),
+        }
+    }
+}
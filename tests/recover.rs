@@ -0,0 +1,180 @@
+extern crate lzip;
+
+use std::fs;
+use std::io::prelude::*;
+use std::io::Cursor;
+
+use lzip::format::TRAILER_SIZE;
+use lzip::read::RecoveringLzDecoder;
+use lzip::{Error, MergeError, RepairError};
+
+fn testsuite(name: &str) -> Vec<u8> {
+    let dir = concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/lzip-sys/lzlib-1.13/testsuite/"
+    );
+    fs::read(format!("{}{}", dir, name)).unwrap()
+}
+
+#[test]
+fn repair_single_byte() {
+    let original = testsuite("test.txt.lz");
+    let mut damaged = original.clone();
+    damaged[3000] ^= 0x55;
+
+    let mut output = Vec::new();
+    let repairs = lzip::repair(&mut Cursor::new(&damaged), &mut output).unwrap();
+    assert_eq!(output, original);
+    assert_eq!(repairs.len(), 1);
+    assert_eq!(repairs[0].member, 0);
+    assert_eq!(repairs[0].pos, 3000);
+    assert_eq!(repairs[0].old, original[3000] ^ 0x55);
+    assert_eq!(repairs[0].new, original[3000]);
+}
+
+#[test]
+fn repair_trailer_byte() {
+    let original = testsuite("test.txt.lz");
+    let pos = original.len() - TRAILER_SIZE + 2;
+    let mut damaged = original.clone();
+    damaged[pos] ^= 0x01;
+
+    let mut output = Vec::new();
+    let repairs = lzip::repair(&mut Cursor::new(&damaged), &mut output).unwrap();
+    assert_eq!(output, original);
+    assert_eq!(repairs.len(), 1);
+    assert_eq!(repairs[0].pos, pos as u64);
+}
+
+#[test]
+fn repair_two_bytes() {
+    let mut damaged = testsuite("test.txt.lz");
+    damaged[1000] ^= 0x55;
+    damaged[1500] ^= 0x55;
+
+    let e = lzip::repair(&mut Cursor::new(&damaged), &mut Vec::new()).unwrap_err();
+    let e = e.into_inner().unwrap().downcast::<RepairError>().unwrap();
+    assert_eq!(
+        *e,
+        RepairError::Unrepairable {
+            member: 0,
+            member_offset: 0
+        }
+    );
+}
+
+#[test]
+fn merge_copies_damaged_in_different_places() {
+    let original = testsuite("test.txt.lz");
+    let mut a = original.clone();
+    a[1000] ^= 0x55;
+    let mut b = original.clone();
+    b[3000] ^= 0x55;
+
+    let mut copies = [Cursor::new(&a), Cursor::new(&b)];
+    let mut output = Vec::new();
+    let size = lzip::merge(&mut copies, &mut output).unwrap();
+    assert_eq!(size, original.len() as u64);
+    assert_eq!(output, original);
+}
+
+#[test]
+fn merge_takes_each_member_from_a_valid_copy() {
+    let fox = testsuite("fox.lz");
+    let text = testsuite("test.txt.lz");
+    let mut original = fox.clone();
+    original.extend_from_slice(&text);
+    original.extend_from_slice(&fox);
+
+    // The first copy has a damaged middle member, the second a damaged
+    // last member.
+    let mut a = original.clone();
+    a[fox.len() + 2000] ^= 0x01;
+    let mut b = original.clone();
+    let last = original.len() - TRAILER_SIZE - 10;
+    b[last] ^= 0x01;
+
+    let mut copies = [Cursor::new(&a), Cursor::new(&b)];
+    let mut output = Vec::new();
+    lzip::merge(&mut copies, &mut output).unwrap();
+    assert_eq!(output, original);
+}
+
+#[test]
+fn merge_copies_damaged_in_the_same_place() {
+    let original = testsuite("test.txt.lz");
+    let mut a = original.clone();
+    a[1000] ^= 0x55;
+    let mut b = original.clone();
+    b[1000] ^= 0xAA;
+
+    let mut copies = [Cursor::new(&a), Cursor::new(&b)];
+    let e = lzip::merge(&mut copies, &mut Vec::new()).unwrap_err();
+    let e = e.into_inner().unwrap().downcast::<MergeError>().unwrap();
+    assert_eq!(
+        *e,
+        MergeError::Unrecoverable {
+            member: 0,
+            member_offset: 0
+        }
+    );
+}
+
+fn fox_text_fox() -> (Vec<u8>, Vec<u8>, Vec<u8>) {
+    let fox = testsuite("fox.lz");
+    let text = testsuite("test.txt.lz");
+    let mut lz = fox.clone();
+    lz.extend_from_slice(&text);
+    lz.extend_from_slice(&fox);
+    (lz, fox, text)
+}
+
+#[test]
+fn recovering_decoder_skips_corrupt_middle_member() {
+    let (mut lz, fox, text) = fox_text_fox();
+    lz[fox.len() + 2000] ^= 0x55;
+    let fox_data = b"The quick brown fox jumps over the lazy dog.\n";
+
+    let mut decoder = RecoveringLzDecoder::new(&lz[..]);
+    let mut out = Vec::new();
+    decoder.read_to_end(&mut out).unwrap();
+
+    let damage = decoder.damage();
+    assert_eq!(damage.len(), 1);
+    let end = (fox.len() + text.len()) as u64;
+    assert_eq!(damage[0].compressed_range(), fox.len() as u64..end);
+    assert_eq!(damage[0].data_offset, fox_data.len() as u64);
+    assert!(damage[0].data_size < testsuite("test.txt").len() as u64);
+    assert_eq!(damage[0].error, Error::Data);
+
+    // The output holds the data of the intact members around whatever was
+    // decoded of the damaged one.
+    let suspect = damage[0].data_range();
+    assert_eq!(out.len() as u64, suspect.end + fox_data.len() as u64);
+    assert_eq!(&out[..suspect.start as usize], &fox_data[..]);
+    assert_eq!(&out[suspect.end as usize..], &fox_data[..]);
+    // Only the data decoded before the corrupt byte is correct.
+    let text_data = testsuite("test.txt");
+    let suspect = &out[suspect.start as usize..];
+    assert_eq!(&suspect[..1000], &text_data[..1000]);
+}
+
+#[test]
+fn recovering_decoder_reports_truncated_last_member() {
+    let (lz, fox, _) = fox_text_fox();
+    let cut = lz.len() - 30;
+    let fox_data = b"The quick brown fox jumps over the lazy dog.\n";
+
+    let mut decoder = RecoveringLzDecoder::new(&lz[..cut]);
+    let mut out = Vec::new();
+    decoder.read_to_end(&mut out).unwrap();
+
+    let damage = decoder.damage();
+    assert_eq!(damage.len(), 1);
+    let start = (lz.len() - fox.len()) as u64;
+    assert_eq!(damage[0].compressed_range(), start..cut as u64);
+    let data_offset = (fox_data.len() + testsuite("test.txt").len()) as u64;
+    assert_eq!(damage[0].data_offset, data_offset);
+    assert_eq!(out.len() as u64, damage[0].data_range().end);
+    assert!(matches!(damage[0].error, Error::UnexpectedEof { .. }));
+}
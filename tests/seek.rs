@@ -0,0 +1,121 @@
+extern crate lzip;
+
+use std::fs;
+use std::io;
+use std::io::prelude::*;
+use std::io::{Cursor, SeekFrom};
+
+use lzip::read::SeekableLzDecoder;
+
+fn testsuite(name: &str) -> Vec<u8> {
+    let dir = concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/lzip-sys/lzlib-1.13/testsuite/"
+    );
+    fs::read(format!("{}{}", dir, name)).unwrap()
+}
+
+const FOX: &[u8] = b"The quick brown fox jumps over the lazy dog.\n";
+
+/// Returns a file made of fox.lz, test.txt.lz and fox.lz again, along with
+/// its decompressed data.
+fn three_members() -> (Vec<u8>, Vec<u8>) {
+    let fox = testsuite("fox.lz");
+    let mut lz = fox.clone();
+    lz.extend_from_slice(&testsuite("test.txt.lz"));
+    lz.extend_from_slice(&fox);
+    let mut data = FOX.to_vec();
+    data.extend_from_slice(&testsuite("test.txt"));
+    data.extend_from_slice(FOX);
+    (lz, data)
+}
+
+#[test]
+fn seekable_decoder_reads_across_members() {
+    let (lz, data) = three_members();
+    let mut decoder = SeekableLzDecoder::new(Cursor::new(&lz)).unwrap();
+    assert_eq!(decoder.index().members().len(), 3);
+
+    let mut buf = vec![0; 100];
+    for &pos in &[0, 20, 10_000, 36_000, 5_000] {
+        assert_eq!(decoder.seek(SeekFrom::Start(pos)).unwrap(), pos);
+        decoder.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf[..], &data[pos as usize..pos as usize + 100]);
+    }
+
+    let end = decoder.seek(SeekFrom::End(-10)).unwrap();
+    assert_eq!(end, data.len() as u64 - 10);
+    let mut rest = Vec::new();
+    decoder.read_to_end(&mut rest).unwrap();
+    assert_eq!(rest, &data[data.len() - 10..]);
+
+    decoder.seek(SeekFrom::Start(0)).unwrap();
+    let mut all = Vec::new();
+    decoder.read_to_end(&mut all).unwrap();
+    assert_eq!(all, data);
+}
+
+#[test]
+fn seekable_decoder_checks_trailers() {
+    // fox_bcrc.lz decodes fine but its trailer holds a wrong CRC, which
+    // must be noticed before the last byte of the member is returned.
+    let bcrc = testsuite("fox_bcrc.lz");
+    let mut decoder = SeekableLzDecoder::new(Cursor::new(&bcrc)).unwrap();
+    let mut buf = vec![0; FOX.len()];
+    let e = decoder.read_exact(&mut buf).unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+
+    let mut decoder = SeekableLzDecoder::new(Cursor::new(&bcrc)).unwrap();
+    let e = decoder.read_to_end(&mut Vec::new()).unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+
+    // Leaving a member part way checks the rest of it as well.
+    let mut lz = bcrc.clone();
+    lz.extend_from_slice(&testsuite("fox.lz"));
+    let mut decoder = SeekableLzDecoder::new(Cursor::new(&lz)).unwrap();
+    decoder.read_exact(&mut buf[..10]).unwrap();
+    assert_eq!(&buf[..10], &FOX[..10]);
+    decoder.seek(SeekFrom::Start(FOX.len() as u64)).unwrap();
+    let e = decoder.read(&mut buf).unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+
+    // Members before the damaged one read fine.
+    let mut lz = testsuite("fox.lz");
+    lz.extend_from_slice(&bcrc);
+    let mut decoder = SeekableLzDecoder::new(Cursor::new(&lz)).unwrap();
+    decoder.read_exact(&mut buf).unwrap();
+    assert_eq!(&buf[..], FOX);
+    assert!(decoder.read_exact(&mut buf).is_err());
+}
+
+#[test]
+fn decompress_range_checks_overlapping_members() {
+    let (lz, data) = three_members();
+    let mut out = Vec::new();
+    let n = lzip::read::decompress_range(Cursor::new(&lz), 40..10_000, &mut out).unwrap();
+    assert_eq!(n, 9_960);
+    assert_eq!(out, &data[40..10_000]);
+
+    // Break the CRC of the middle member, test.txt.lz.
+    let mut damaged = lz.clone();
+    let crc = testsuite("fox.lz").len() + testsuite("test.txt.lz").len() - 20;
+    damaged[crc] ^= 1;
+    let text = testsuite("test.txt").len() as u64;
+    let fox = FOX.len() as u64;
+    for range in &[
+        fox + 100..fox + 200,
+        0..fox + 1,
+        fox + text - 1..fox + text + 1,
+    ] {
+        let e = lzip::read::decompress_range(Cursor::new(&damaged), range.clone(), &mut Vec::new())
+            .unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidData, "{:?}", range);
+    }
+
+    // The members around it are fine on their own.
+    for range in &[0..fox, fox + text..fox + text + fox] {
+        let mut out = Vec::new();
+        lzip::read::decompress_range(Cursor::new(&damaged), range.clone(), &mut out).unwrap();
+        assert_eq!(out, FOX);
+    }
+}
@@ -0,0 +1,89 @@
+extern crate lzip;
+
+use std::cell::RefCell;
+use std::fs;
+use std::io;
+use std::io::prelude::*;
+use std::io::Cursor;
+use std::path::PathBuf;
+use std::process;
+use std::rc::Rc;
+
+fn testsuite(name: &str) -> Vec<u8> {
+    let dir = concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/lzip-sys/lzlib-1.13/testsuite/"
+    );
+    fs::read(format!("{}{}", dir, name)).unwrap()
+}
+
+const FOX: &[u8] = b"The quick brown fox jumps over the lazy dog.\n";
+
+/// Returns a file made of fox.lz, test.txt.lz and fox.lz again, along with
+/// its decompressed data.
+fn three_members() -> (Vec<u8>, Vec<u8>) {
+    let fox = testsuite("fox.lz");
+    let mut lz = fox.clone();
+    lz.extend_from_slice(&testsuite("test.txt.lz"));
+    lz.extend_from_slice(&fox);
+    let mut data = FOX.to_vec();
+    data.extend_from_slice(&testsuite("test.txt"));
+    data.extend_from_slice(FOX);
+    (lz, data)
+}
+
+/// A path in the temporary directory unique to this process and `name`.
+fn temp_path(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("lzip-tests-{}-{}", process::id(), name));
+    fs::create_dir_all(&dir).unwrap();
+    dir.join("file.lz")
+}
+
+/// A writer whose contents can be inspected after it has been given away.
+#[derive(Clone, Default)]
+struct Shared(Rc<RefCell<Vec<u8>>>);
+
+impl Write for Shared {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn split_copies_each_member() {
+    let (mut lz, _) = three_members();
+    lz.extend_from_slice(b"trailing data");
+    let fox = testsuite("fox.lz");
+    let text = testsuite("test.txt.lz");
+
+    let mut outputs = Vec::new();
+    let index = lzip::split(&mut Cursor::new(&lz), |_, _| {
+        let w = Shared::default();
+        outputs.push(w.clone());
+        Ok(w)
+    })
+    .unwrap();
+    assert_eq!(index.members().len(), 3);
+    let outputs: Vec<_> = outputs.iter().map(|w| w.0.borrow().clone()).collect();
+    assert_eq!(outputs, [fox.clone(), text, fox]);
+}
+
+#[test]
+fn split_to_files_names_members() {
+    let (lz, _) = three_members();
+    let path = temp_path("split");
+    fs::write(&path, &lz).unwrap();
+    lzip::split_to_files(&path).unwrap();
+    let rec = |n: &str| fs::read(path.with_file_name(n)).unwrap();
+    assert_eq!(rec("rec01file.lz"), testsuite("fox.lz"));
+    assert_eq!(rec("rec02file.lz"), testsuite("test.txt.lz"));
+    assert_eq!(rec("rec03file.lz"), testsuite("fox.lz"));
+
+    // Existing files are not overwritten.
+    assert!(lzip::split_to_files(&path).is_err());
+    fs::remove_dir_all(path.parent().unwrap()).unwrap();
+}
@@ -0,0 +1,165 @@
+extern crate lzip;
+
+use std::fs::{self, File, OpenOptions};
+use std::io;
+use std::io::prelude::*;
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+use std::process;
+
+use lzip::format::Error as FormatError;
+use lzip::index::Index;
+use lzip::read::MultiLzDecoder;
+use lzip::write::{LzAppender, LzEncoder};
+use lzip::{DecodeError, Error};
+
+fn testsuite(name: &str) -> Vec<u8> {
+    let dir = concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/lzip-sys/lzlib-1.13/testsuite/"
+    );
+    fs::read(format!("{}{}", dir, name)).unwrap()
+}
+
+/// A path in the temporary directory unique to this process and `name`.
+fn temp_path(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("lzip-tests-{}-{}", process::id(), name));
+    fs::create_dir_all(&dir).unwrap();
+    dir.join("file.lz")
+}
+
+fn open(path: &Path) -> File {
+    OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(path)
+        .unwrap()
+}
+
+fn decompress(lz: &[u8]) -> Vec<u8> {
+    let mut out = Vec::new();
+    MultiLzDecoder::new(lz).read_to_end(&mut out).unwrap();
+    out
+}
+
+/// Writes `data` a line at a time, flushing after each line.
+fn write_lines<W: Write>(w: &mut W, data: &[u8]) {
+    for line in data.split_inclusive(|&b| b == b'\n') {
+        w.write_all(line).unwrap();
+        w.flush().unwrap();
+    }
+}
+
+#[test]
+fn encoder_round_trip() {
+    let text = testsuite("test.txt");
+    for &level in &[0, 6, 9] {
+        let mut encoder = LzEncoder::new(Vec::new(), level);
+        write_lines(&mut encoder, &text);
+        assert_eq!(encoder.total_in(), text.len() as u64);
+        let lz = encoder.finish().unwrap();
+        assert_eq!(decompress(&lz), text, "level {}", level);
+
+        // Flushing doesn't leave markers that make the member look
+        // inconsistent.
+        let index = Index::new(&mut Cursor::new(&lz)).unwrap();
+        assert_eq!(index.members().len(), 1);
+        assert_eq!(index.data_size(), text.len() as u64);
+    }
+
+    // Not even when flushing after every byte.
+    let fox = b"The quick brown fox jumps over the lazy dog.\n";
+    let mut encoder = LzEncoder::new(Vec::new(), 6);
+    for b in fox.chunks(1) {
+        encoder.write_all(b).unwrap();
+        encoder.flush().unwrap();
+    }
+    let lz = encoder.finish().unwrap();
+    assert_eq!(decompress(&lz), fox);
+    Index::new(&mut Cursor::new(&lz)).unwrap();
+
+    // Nothing written still makes a valid, empty member.
+    let lz = LzEncoder::new(Vec::new(), 6).finish().unwrap();
+    assert_eq!(decompress(&lz), b"");
+}
+
+#[test]
+fn append_reopen_append() {
+    let path = temp_path("append");
+    File::create(&path).unwrap();
+    let text = testsuite("test.txt");
+    let (a, b) = text.split_at(10_000);
+
+    let mut appender = LzAppender::new(open(&path)).unwrap();
+    let mut encoder = appender.append_member(6).unwrap();
+    write_lines(&mut encoder, a);
+    encoder.finish().unwrap();
+    drop(appender);
+
+    let mut appender = LzAppender::new(open(&path)).unwrap();
+    assert_eq!(appender.trailing_size(), 0);
+    let mut encoder = appender.append_member(0).unwrap();
+    write_lines(&mut encoder, b);
+    encoder.finish().unwrap();
+    drop(appender);
+
+    // And once more, to a file written by the appender.
+    let mut appender = LzAppender::new(open(&path)).unwrap();
+    appender.append_member(6).unwrap().finish().unwrap();
+    drop(appender);
+
+    let lz = fs::read(&path).unwrap();
+    assert_eq!(decompress(&lz), text);
+    let index = Index::new(&mut Cursor::new(&lz)).unwrap();
+    let sizes: Vec<u64> = index.members().iter().map(|m| m.data_size).collect();
+    assert_eq!(sizes, [a.len() as u64, b.len() as u64, 0]);
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn appending_after_trailing_data_is_refused() {
+    let path = temp_path("trailing");
+    let fox = testsuite("fox.lz");
+    let mut lz = fox.clone();
+    lz.extend_from_slice(b"trailing data");
+    fs::write(&path, &lz).unwrap();
+
+    let mut appender = LzAppender::new(open(&path)).unwrap();
+    assert_eq!(appender.trailing_size(), 13);
+    let e = appender.append_member(6).err().unwrap();
+    assert_eq!(e.kind(), io::ErrorKind::InvalidInput);
+    // The file is left alone.
+    assert_eq!(fs::read(&path).unwrap(), lz);
+
+    appender.truncate_trailing_data().unwrap();
+    assert_eq!(appender.trailing_size(), 0);
+    appender.append_member(6).unwrap().finish().unwrap();
+    drop(appender);
+    let mut expected = fox.clone();
+    expected.extend_from_slice(&LzEncoder::new(Vec::new(), 6).finish().unwrap());
+    assert_eq!(fs::read(&path).unwrap(), expected);
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn damaged_last_member_is_reported() {
+    let path = temp_path("damaged");
+    let fox = testsuite("fox.lz");
+    let mut lz = fox.clone();
+    lz.extend_from_slice(&testsuite("fox_bcrc.lz"));
+    fs::write(&path, &lz).unwrap();
+
+    let e = LzAppender::new(open(&path)).err().unwrap();
+    assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+    let e = *e.get_ref().unwrap().downcast_ref::<DecodeError>().unwrap();
+    assert_eq!(e.member, 1);
+    assert_eq!(e.member_offset, fox.len() as u64);
+    assert_eq!(e.total_out, 2 * 45);
+    assert!(matches!(
+        e.error,
+        Error::Format(FormatError::CrcMismatch { .. })
+    ));
+    fs::remove_file(&path).unwrap();
+}